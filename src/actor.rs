@@ -0,0 +1,346 @@
+//! The task that owns a virtual router's state. Everything that acts on
+//! the router -- received packets, tracked objects, sync group transitions,
+//! signals -- sends it an [`Input`] rather than locking it, and the task
+//! works through them one at a time alongside its own timers. It's also
+//! where the state machine's `Action`s are carried out, awaiting any
+//! netlink/nft change in between instead of blocking a worker thread on
+//! it.
+use std::net::IpAddr;
+use std::time::Instant;
+
+use tokio::sync::mpsc::error::TrySendError;
+use tokio::sync::{mpsc, watch};
+use tokio::task::JoinHandle;
+use tokio::time;
+
+use crate::AddressAction;
+use crate::general::{
+    accept_mode_action, delete_link, forget_virtual_mac,
+    virtual_address_action, virtual_mac_discard_action,
+};
+use crate::packet::{ArpPacket, NdpNeighborSolicitation, VrrpPacket};
+use crate::pkt::handlers::{
+    accept_advert, handle_incoming_arp_pkt, handle_incoming_ndp_pkt,
+};
+use crate::router::VirtualRouter;
+use crate::state_machine::{Action, Event, State};
+
+/// Room for a burst of traffic while the router is waiting on a netlink
+/// change; packets beyond it are dropped (see `RouterHandle::offer`).
+const INBOX_CAPACITY: usize = 256;
+
+#[derive(Debug)]
+pub(crate) enum Input {
+    /// An advert `dispatch` matched to this router, with the source address
+    /// and TTL/hop-limit it arrived with.
+    Vrrp {
+        packet: VrrpPacket,
+        src: IpAddr,
+        ttl: u8,
+    },
+    Arp {
+        frame_dst: [u8; 6],
+        frame_src: [u8; 6],
+        packet: ArpPacket,
+    },
+    Ndp(NdpNeighborSolicitation),
+    /// New health for one or more tracked objects, applied together so a
+    /// batch only moves the priority (and sends an advert) once.
+    Tracked(Vec<TrackUpdate>),
+    Event(Event),
+    /// Log `VirtualRouter::status`.
+    Status,
+}
+
+/// See `VirtualRouter::update_tracked`.
+#[derive(Debug)]
+pub(crate) struct TrackUpdate {
+    pub(crate) key: String,
+    pub(crate) weight: Option<u8>,
+    pub(crate) healthy: bool,
+}
+
+/// A router's inbox, and a view of the state it's in.
+#[derive(Clone, Debug)]
+pub(crate) struct RouterHandle {
+    name: String,
+    tx: mpsc::Sender<Input>,
+    state: watch::Receiver<State>,
+}
+
+impl RouterHandle {
+    /// Queues a received packet without waiting: one router falling behind
+    /// mustn't hold up a receive loop every router on the interface shares,
+    /// and VRRP copes with the odd lost packet anyway.
+    pub(crate) fn offer(&self, input: Input) {
+        if let Err(TrySendError::Full(_)) = self.tx.try_send(input) {
+            log::warn!("({}) inbox full; dropping packet", self.name);
+        }
+    }
+
+    /// Queues `input`, waiting for room if need be. Once the router has
+    /// shut down there's nobody left to tell, so that isn't an error.
+    pub(crate) async fn send(&self, input: Input) {
+        let _ = self.tx.send(input).await;
+    }
+
+    pub(crate) fn state(&self) -> State {
+        *self.state.borrow()
+    }
+}
+
+#[cfg(test)]
+impl RouterHandle {
+    /// A handle with no router behind it: whatever it's sent lands in the
+    /// returned inbox.
+    pub(crate) fn detached(name: &str) -> (Self, mpsc::Receiver<Input>) {
+        let (tx, inbox) = mpsc::channel(INBOX_CAPACITY);
+        let (_, state) = watch::channel(State::Init);
+        let handle = Self {
+            name: name.to_string(),
+            tx,
+            state,
+        };
+        (handle, inbox)
+    }
+}
+
+/// Hands `vrouter` over to a task of its own, which runs until it's sent
+/// `Event::Shutdown`.
+pub(crate) fn spawn(vrouter: VirtualRouter) -> (RouterHandle, JoinHandle<()>) {
+    let (tx, inbox) = mpsc::channel(INBOX_CAPACITY);
+    let (state_tx, state) = watch::channel(vrouter.fsm.state);
+    let handle = RouterHandle {
+        name: vrouter.name.clone(),
+        tx,
+        state,
+    };
+    (handle, tokio::spawn(run(vrouter, inbox, state_tx)))
+}
+
+async fn run(
+    mut vrouter: VirtualRouter,
+    mut inbox: mpsc::Receiver<Input>,
+    state: watch::Sender<State>,
+) {
+    // Anything armed before the router was handed over, i.e. a
+    // startup_delay.
+    let mut wakeup = vrouter.fsm.next_deadline();
+    loop {
+        let mut shutdown = false;
+        tokio::select! {
+            input = inbox.recv() => {
+                let Some(input) = input else { break };
+                shutdown = matches!(input, Input::Event(Event::Shutdown));
+                handle(&mut vrouter, input, &mut wakeup).await;
+            }
+            _ = sleep_until(wakeup) => {
+                step(&mut vrouter, Event::Timer, &mut wakeup).await;
+            }
+        }
+        state.send_if_modified(|current| {
+            let changed = *current != vrouter.fsm.state;
+            *current = vrouter.fsm.state;
+            changed
+        });
+        if shutdown {
+            tear_down(&vrouter).await;
+            break;
+        }
+    }
+}
+
+async fn sleep_until(deadline: Option<Instant>) {
+    match deadline {
+        Some(deadline) => {
+            time::sleep_until(time::Instant::from_std(deadline)).await;
+        }
+        None => std::future::pending().await,
+    }
+}
+
+async fn handle(
+    vrouter: &mut VirtualRouter,
+    input: Input,
+    wakeup: &mut Option<Instant>,
+) {
+    match input {
+        Input::Vrrp { packet, src, ttl } => {
+            if let Some(advert) = accept_advert(&packet, src, ttl, vrouter) {
+                step(vrouter, Event::Advert(advert), wakeup).await;
+            }
+        }
+        Input::Arp {
+            frame_dst,
+            frame_src,
+            packet,
+        } => handle_incoming_arp_pkt(frame_dst, frame_src, &packet, vrouter),
+        Input::Ndp(ns) => handle_incoming_ndp_pkt(&ns, vrouter),
+        Input::Tracked(updates) => {
+            let mut changed = false;
+            for update in updates {
+                changed |= vrouter.update_tracked(
+                    &update.key,
+                    update.weight,
+                    update.healthy,
+                );
+            }
+            if changed {
+                step(vrouter, Event::PriorityChange, wakeup).await;
+            }
+        }
+        Input::Event(event) => step(vrouter, event, wakeup).await,
+        Input::Status => log::info!("({}) {}", vrouter.name, vrouter.status()),
+    }
+}
+
+/// Runs `event` through the state machine and carries out what it decides.
+async fn step(
+    vrouter: &mut VirtualRouter,
+    event: Event,
+    wakeup: &mut Option<Instant>,
+) {
+    for action in vrouter.handle(event, Instant::now()) {
+        match action {
+            Action::SendAdvert { priority } => {
+                vrouter.send_advertisement(priority);
+            }
+            Action::AddAddresses => add_virtual_addresses(vrouter).await,
+            Action::DeleteAddresses => delete_virtual_addresses(vrouter).await,
+            // See `core_tasks::announce_process`.
+            Action::Announce => vrouter.announce.notify_one(),
+            Action::DiscardVirtualMac => {
+                virtual_mac_discard_action(true, vrouter).await;
+            }
+            Action::AcceptVirtualMac => {
+                virtual_mac_discard_action(false, vrouter).await;
+            }
+            Action::Publish(state) => {
+                if let Some(group) = &vrouter.sync_group {
+                    group.publish(&vrouter.name, state);
+                }
+            }
+            Action::ArmTimer(deadline) => *wakeup = deadline,
+        }
+    }
+}
+
+async fn add_virtual_addresses(vrouter: &VirtualRouter) {
+    virtual_address_action(
+        AddressAction::Add,
+        &vrouter.str_ipv4_addresses(),
+        &vrouter.mac_vlan_interface_v4,
+    )
+    .await;
+    if let Some(v6_iface) = &vrouter.mac_vlan_interface_v6 {
+        virtual_address_action(
+            AddressAction::Add,
+            &vrouter.str_ipv6_addresses(),
+            v6_iface,
+        )
+        .await;
+    }
+    accept_mode_action(AddressAction::Add, vrouter).await;
+}
+
+async fn delete_virtual_addresses(vrouter: &VirtualRouter) {
+    accept_mode_action(AddressAction::Delete, vrouter).await;
+    virtual_address_action(
+        AddressAction::Delete,
+        &vrouter.str_ipv4_addresses(),
+        &vrouter.mac_vlan_interface_v4,
+    )
+    .await;
+    if let Some(v6_iface) = &vrouter.mac_vlan_interface_v6 {
+        virtual_address_action(
+            AddressAction::Delete,
+            &vrouter.str_ipv6_addresses(),
+            v6_iface,
+        )
+        .await;
+    }
+}
+
+/// Removes the mac-vlans once the router has shut down.
+async fn tear_down(vrouter: &VirtualRouter) {
+    // Without a virtual MAC the "mac-vlans" are the parent interface
+    // itself, which is never ours to remove. The addresses the host
+    // already had on it were left out of every delete as well (see
+    // `VirtualRouter::host_addresses`).
+    if !vrouter.use_virtual_mac {
+        log::info!("({}) shut down", vrouter.name);
+        return;
+    }
+    forget_virtual_mac(vrouter).await;
+    // Only actually removes an interface once no addresses -- ours or a
+    // sibling instance's -- remain on it; see `general::delete_link`.
+    let backend = vrouter.link_backend;
+    delete_link(backend, &vrouter.mac_vlan_interface_v4).await;
+    if let Some(v6_iface) = &vrouter.mac_vlan_interface_v6 {
+        delete_link(backend, v6_iface).await;
+    }
+    log::info!(
+        "({}) shut down, {backend} {} torn down",
+        vrouter.name,
+        vrouter.mac_vlan_interface_v4
+    );
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::VrrpVersion;
+    use crate::config::InitialState;
+    use crate::router::tests::sample;
+
+    // The default current_thread runtime: nothing the router does may
+    // block its worker.
+    #[tokio::test]
+    async fn failing_tracked_object_faults_a_backup() {
+        let mut vrouter = sample(VrrpVersion::V3, 100);
+        vrouter.fsm.state = State::Backup;
+        // Nothing of ours to tear down at shutdown.
+        vrouter.use_virtual_mac = false;
+        let (router, task) = spawn(vrouter);
+        let mut state = router.state.clone();
+
+        router
+            .send(Input::Tracked(vec![TrackUpdate {
+                key: "interface eth1".to_string(),
+                weight: None,
+                healthy: false,
+            }]))
+            .await;
+        state.wait_for(|s| *s == State::Fault).await.unwrap();
+        assert_eq!(router.state(), State::Fault);
+
+        router.send(Input::Event(Event::Shutdown)).await;
+        task.await.unwrap();
+    }
+
+    #[tokio::test]
+    async fn failure_reported_before_startup_starts_in_fault() {
+        let mut vrouter = sample(VrrpVersion::V3, 100);
+        vrouter.initial_state = InitialState::Master;
+        vrouter.use_virtual_mac = false;
+        let (router, task) = spawn(vrouter);
+        let mut state = router.state.clone();
+
+        // What `lib::run` does once the trackers have reported in.
+        router
+            .send(Input::Tracked(vec![TrackUpdate {
+                key: "interface eth1".to_string(),
+                weight: None,
+                healthy: false,
+            }]))
+            .await;
+        router.send(Input::Event(Event::Startup)).await;
+
+        let left_init = state.wait_for(|s| *s != State::Init).await.unwrap();
+        assert_eq!(*left_init, State::Fault);
+        drop(left_init);
+
+        router.send(Input::Event(Event::Shutdown)).await;
+        task.await.unwrap();
+    }
+}
@@ -0,0 +1,148 @@
+//! Gratuitous ARP / unsolicited Neighbor Advertisement scheduling: how many
+//! times a new MASTER announces its virtual IP(s), how often it refreshes
+//! them afterwards, and a rate cap shared by every instance in the process.
+use std::sync::{LazyLock, Mutex};
+use std::time::{Duration, Instant};
+
+/// Announcement frames (one per virtual IP per round) the whole process may
+/// send per second, across every instance. A box with hundreds of VIPs
+/// spreads its announcements out instead of flooding the segment.
+const MAX_ANNOUNCEMENTS_PER_SEC: u32 = 200;
+
+static LIMITER: LazyLock<Mutex<TokenBucket>> = LazyLock::new(|| {
+    Mutex::new(TokenBucket::new(MAX_ANNOUNCEMENTS_PER_SEC, Instant::now()))
+});
+
+/// Per-instance announcement settings (the `garp_*` config options).
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) struct GarpSettings {
+    /// Announcement rounds sent on becoming MASTER; at least 1.
+    pub(crate) repeat: u8,
+    /// Delay between those rounds.
+    pub(crate) interval_ms: u16,
+    /// Seconds between refresh rounds while MASTER; 0 disables them.
+    pub(crate) refresh: u16,
+    /// Send gratuitous ARPs as replies rather than requests.
+    pub(crate) reply: bool,
+}
+
+impl Default for GarpSettings {
+    fn default() -> Self {
+        Self {
+            repeat: 1,
+            interval_ms: 200,
+            refresh: 0,
+            reply: false,
+        }
+    }
+}
+
+impl GarpSettings {
+    pub(crate) fn interval(&self) -> Duration {
+        Duration::from_millis(self.interval_ms as u64)
+    }
+
+    pub(crate) fn refresh(&self) -> Option<Duration> {
+        (self.refresh > 0).then(|| Duration::from_secs(self.refresh as u64))
+    }
+}
+
+/// Waits until the process-wide rate cap allows one more announcement
+/// frame to go out.
+pub(crate) async fn acquire() {
+    loop {
+        let wait = match LIMITER.lock() {
+            Ok(mut bucket) => match bucket.take(Instant::now()) {
+                Ok(()) => return,
+                Err(wait) => wait,
+            },
+            // Nothing in the bucket can be left half-updated; better to
+            // send unthrottled than not announce at all.
+            Err(_) => return,
+        };
+        tokio::time::sleep(wait).await;
+    }
+}
+
+/// A token bucket refilling at `rate` tokens per second, holding at most
+/// one second's worth.
+#[derive(Debug)]
+struct TokenBucket {
+    rate: f64,
+    tokens: f64,
+    last: Instant,
+}
+
+impl TokenBucket {
+    fn new(rate: u32, now: Instant) -> Self {
+        Self {
+            rate: rate as f64,
+            tokens: rate as f64,
+            last: now,
+        }
+    }
+
+    /// Takes one token, or returns how long until one is available.
+    fn take(&mut self, now: Instant) -> Result<(), Duration> {
+        let elapsed = now.saturating_duration_since(self.last).as_secs_f64();
+        self.tokens = (self.tokens + elapsed * self.rate).min(self.rate);
+        self.last = now;
+        if self.tokens >= 1.0 {
+            self.tokens -= 1.0;
+            return Ok(());
+        }
+        Err(Duration::from_secs_f64((1.0 - self.tokens) / self.rate))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn bucket_allows_a_burst_of_one_seconds_worth() {
+        let now = Instant::now();
+        let mut bucket = TokenBucket::new(3, now);
+
+        assert!(bucket.take(now).is_ok());
+        assert!(bucket.take(now).is_ok());
+        assert!(bucket.take(now).is_ok());
+        assert!(bucket.take(now).is_err());
+    }
+
+    #[test]
+    fn bucket_refills_at_its_rate() {
+        let now = Instant::now();
+        let mut bucket = TokenBucket::new(10, now);
+        for _ in 0..10 {
+            bucket.take(now).unwrap();
+        }
+
+        let wait = bucket.take(now).unwrap_err();
+        assert!(wait <= Duration::from_millis(100));
+        assert!(bucket.take(now + Duration::from_millis(100)).is_ok());
+    }
+
+    #[test]
+    fn bucket_never_banks_more_than_one_second() {
+        let now = Instant::now();
+        let mut bucket = TokenBucket::new(2, now);
+        let later = now + Duration::from_secs(60);
+
+        assert!(bucket.take(later).is_ok());
+        assert!(bucket.take(later).is_ok());
+        assert!(bucket.take(later).is_err());
+    }
+
+    #[test]
+    fn refresh_of_zero_is_disabled() {
+        let garp = GarpSettings::default();
+        assert_eq!(garp.refresh(), None);
+
+        let garp = GarpSettings {
+            refresh: 30,
+            ..garp
+        };
+        assert_eq!(garp.refresh(), Some(Duration::from_secs(30)));
+    }
+}
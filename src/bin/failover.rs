@@ -1,6 +1,6 @@
 use clap::Parser;
 use failover_vr::config::{CliArgs, parse_cli_opts};
-use failover_vr::general::config_to_vr;
+use failover_vr::general::configs_to_vrs;
 use tokio::task::JoinSet;
 
 #[tokio::main]
@@ -18,8 +18,8 @@ async fn main() {
     };
 
     let mut routers_tasks = JoinSet::new();
-    for config in routers_config {
-        let vrouter = config_to_vr(config);
+    // Built together so the members of a sync group share its channel.
+    for vrouter in configs_to_vrs(routers_config) {
         routers_tasks.spawn(async { failover_vr::run(vrouter).await });
     }
 
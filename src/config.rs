@@ -1,11 +1,11 @@
 use std::env;
-use std::ffi::OsStr;
 use std::fs::{File, create_dir_all};
-use std::io::{BufReader, Write};
+use std::io::Write;
+use std::net::IpAddr;
 use std::path::Path;
 use std::str::FromStr;
 
-use clap::Parser;
+use clap::{Parser, ValueEnum};
 use ipnet::IpNet;
 use log::LevelFilter;
 use log4rs::Config as Log4rsConfig;
@@ -41,6 +41,129 @@ fn default_advert_int() -> u8 {
 fn default_preempt_mode() -> bool {
     true
 }
+fn default_accept_mode() -> bool {
+    true
+}
+fn default_use_virtual_mac() -> bool {
+    true
+}
+fn default_script_interval() -> u16 {
+    2
+}
+fn default_script_count() -> u8 {
+    1
+}
+fn default_route_table() -> u32 {
+    254 // RT_TABLE_MAIN
+}
+fn default_garp_repeat() -> u8 {
+    1
+}
+fn default_garp_interval_ms() -> u16 {
+    200
+}
+
+/// Which state an instance leaves INIT for on startup.
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, PartialEq, Eq, Default)]
+#[derive(ValueEnum)]
+#[serde(rename_all = "lowercase")]
+pub(crate) enum InitialState {
+    /// MASTER if we're the address owner, BACKUP otherwise (RFC 3768/5798).
+    #[default]
+    Auto,
+    Backup,
+    Master,
+}
+
+/// The kind of link created on `interface_name` to carry the virtual IP(s).
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, PartialEq, Eq, Default)]
+#[derive(ValueEnum)]
+#[serde(rename_all = "lowercase")]
+pub(crate) enum LinkBackend {
+    /// A mac-vlan per family with the VRRP virtual MAC (RFC 3768/5798).
+    #[default]
+    Macvlan,
+    /// An L2-mode ipvlan per family, sharing the parent's MAC -- for ports
+    /// that only allow one MAC address.
+    Ipvlan,
+}
+
+impl std::fmt::Display for LinkBackend {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        match self {
+            Self::Macvlan => write!(f, "mac-vlan"),
+            Self::Ipvlan => write!(f, "ipvlan"),
+        }
+    }
+}
+
+/// An interface whose carrier this instance's priority depends on.
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq, Eq)]
+pub(crate) struct TrackInterface {
+    pub(crate) name: String,
+    /// Subtracted from the priority while the link is down. Without one, a
+    /// down link puts the instance into FAULT instead.
+    #[serde(default)]
+    pub(crate) weight: Option<u8>,
+}
+
+/// An external health check whose result this instance's priority depends
+/// on, e.g. `pidof haproxy` or `curl -fs http://localhost/health`.
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq, Eq)]
+pub(crate) struct TrackScript {
+    pub(crate) name: String,
+    /// Run through `/bin/sh -c`; healthy when it exits 0 within `timeout`.
+    pub(crate) command: String,
+    /// Seconds between runs.
+    #[serde(default = "default_script_interval")]
+    pub(crate) interval: u16,
+    /// Seconds before a run is killed and counted as a failure. Defaults
+    /// to `interval`.
+    #[serde(default)]
+    pub(crate) timeout: Option<u16>,
+    /// Consecutive successes needed to go from failing back to healthy.
+    #[serde(default = "default_script_count")]
+    pub(crate) rise: u8,
+    /// Consecutive failures needed to go from healthy to failing.
+    #[serde(default = "default_script_count")]
+    pub(crate) fall: u8,
+    /// Subtracted from the priority while failing. Without one, a failing
+    /// script puts the instance into FAULT instead.
+    #[serde(default)]
+    pub(crate) weight: Option<u8>,
+}
+
+/// A kernel route (e.g. the default route, or a prefix learned over BGP)
+/// this instance should only be MASTER while present.
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq, Eq)]
+pub(crate) struct TrackRoute {
+    pub(crate) prefix: IpNet,
+    /// Routing table to look in. Defaults to `main`.
+    #[serde(default = "default_route_table")]
+    pub(crate) table: u32,
+    /// Subtracted from the priority while the route is missing. Without
+    /// one, a missing route puts the instance into FAULT instead.
+    #[serde(default)]
+    pub(crate) weight: Option<u8>,
+}
+
+/// Instances that are always forced into the same MASTER/BACKUP/FAULT
+/// state, e.g. the WAN- and LAN-side VRs of a NAT gateway. Members are
+/// referred to by instance name.
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq, Eq)]
+pub(crate) struct SyncGroup {
+    pub(crate) name: String,
+    pub(crate) instances: Vec<String>,
+}
+
+/// The config file form that has room for sync groups alongside the
+/// instances (the plain array and single-object forms don't).
+#[derive(Debug, Deserialize)]
+struct SectionedConfig {
+    instances: Vec<Config>,
+    #[serde(default)]
+    sync_groups: Vec<SyncGroup>,
+}
 
 #[derive(Debug, Serialize, Deserialize, Clone)]
 pub struct Config {
@@ -54,8 +177,70 @@ pub struct Config {
     pub(crate) priority: u8,
     #[serde(default = "default_advert_int")]
     pub(crate) advert_interval: u8,
+    /// v3-only sub-second override of `advert_interval`, in centiseconds
+    /// (the unit of the v3 wire field). Takes precedence when set.
+    #[serde(default)]
+    pub(crate) advert_interval_cs: Option<u16>,
     #[serde(default = "default_preempt_mode")]
     pub(crate) preempt_mode: bool,
+    /// Seconds a higher-priority BACKUP waits before preempting a
+    /// lower-priority MASTER. 0 preempts immediately.
+    #[serde(default)]
+    pub(crate) preempt_delay: u16,
+    /// RFC 5798 Accept_Mode. Defaults to `true` (unlike the RFC) so that
+    /// existing configs keep answering traffic sent to the virtual IPs.
+    #[serde(default = "default_accept_mode")]
+    pub(crate) accept_mode: bool,
+    /// Seconds to hold in INIT (listening only) before running the startup
+    /// transition.
+    #[serde(default)]
+    pub(crate) startup_delay: u16,
+    #[serde(default)]
+    pub(crate) initial_state: InitialState,
+    #[serde(default)]
+    pub(crate) track_interfaces: Vec<TrackInterface>,
+    #[serde(default)]
+    pub(crate) track_scripts: Vec<TrackScript>,
+    #[serde(default)]
+    pub(crate) track_routes: Vec<TrackRoute>,
+    /// Name of the sync group this instance belongs to. Filled in from the
+    /// file's `sync_groups` section by `assign_sync_groups`.
+    #[serde(skip)]
+    pub(crate) sync_group: Option<String>,
+    /// Peers to send adverts to (and accept them from) by unicast, for
+    /// networks that drop VRRP multicast. Empty keeps multicast.
+    #[serde(default)]
+    pub(crate) unicast_peers: Vec<IpAddr>,
+    /// Source address for unicast adverts of the matching family. Defaults
+    /// to the interface's primary address.
+    #[serde(default)]
+    pub(crate) unicast_src_ip: Option<IpAddr>,
+    /// VRRPv2 simple-text authentication (Auth Type 1) password, up to 8
+    /// bytes. Unset sends and expects Auth Type 0.
+    #[serde(default)]
+    pub(crate) auth_password: Option<String>,
+    /// Gratuitous ARP/NA rounds sent on becoming MASTER, `garp_interval_ms`
+    /// apart. Repeats help switches and hosts that miss the first one.
+    #[serde(default = "default_garp_repeat")]
+    pub(crate) garp_repeat: u8,
+    #[serde(default = "default_garp_interval_ms")]
+    pub(crate) garp_interval_ms: u16,
+    /// Seconds between refresh rounds while MASTER. 0 disables them.
+    #[serde(default)]
+    pub(crate) garp_refresh: u16,
+    /// Send gratuitous ARPs as replies (op 2) rather than requests, for
+    /// hosts that only update their cache from replies.
+    #[serde(default)]
+    pub(crate) garp_reply: bool,
+    /// When false, skips the mac-vlans: the virtual IP(s) go straight on
+    /// `interface_name` and adverts/GARPs use its own MAC, for NICs and
+    /// hypervisors that drop unknown source MACs or don't support macvlan.
+    #[serde(default = "default_use_virtual_mac")]
+    pub(crate) use_virtual_mac: bool,
+    /// Kind of link carrying the virtual IP(s). Unused when
+    /// `use_virtual_mac` is false.
+    #[serde(default)]
+    pub(crate) link_backend: LinkBackend,
     #[serde(default)]
     pub(crate) version: VrrpVersion,
 }
@@ -117,6 +302,13 @@ enum Mode {
         )]
         advert_interval: u8,
 
+        #[arg(
+            long,
+            default_value = None,
+            help = "(VRRPv3 only) Advertisement interval in centiseconds, e.g. 10 for 100ms. Overrides --advert-interval when given."
+        )]
+        advert_interval_cs: Option<u16>,
+
         #[arg(
             long,
             action,
@@ -124,6 +316,102 @@ enum Mode {
         )]
         preempt_mode: bool,
 
+        #[arg(
+            long,
+            default_value = "0",
+            help = "Seconds to wait before preempting a lower priority MASTER (when preempt_mode is true). 0 preempts immediately."
+        )]
+        preempt_delay: u16,
+
+        #[arg(
+            long,
+            default_value = "true",
+            action = clap::ArgAction::Set,
+            help = "When false, a MASTER that isn't the address owner drops traffic addressed to the virtual IP(s) (RFC 5798 Accept_Mode)."
+        )]
+        accept_mode: bool,
+
+        #[arg(
+            long,
+            default_value = "0",
+            help = "Seconds to stay in INIT, only listening, before competing for MASTER."
+        )]
+        startup_delay: u16,
+
+        #[arg(
+            long,
+            value_enum,
+            default_value_t = InitialState::Auto,
+            help = "State to leave INIT for. `auto` starts as MASTER only when this router owns the virtual IP(s). An owner started as `backup` needs a preempt-delay (or no preemption) to stay BACKUP."
+        )]
+        initial_state: InitialState,
+
+        #[arg(
+            long = "unicast-peer",
+            num_args = 1..,
+            help = "Send adverts to (and only accept them from) these peers by unicast instead of multicast. Repeat or list several."
+        )]
+        unicast_peers: Vec<IpAddr>,
+
+        #[arg(
+            long,
+            default_value = None,
+            help = "Source address for unicast adverts. Defaults to the interface's primary address."
+        )]
+        unicast_src_ip: Option<IpAddr>,
+
+        #[arg(
+            long,
+            default_value = None,
+            help = "(VRRPv2 only) Simple-text authentication password, up to 8 characters. Must match on every router."
+        )]
+        auth_password: Option<String>,
+
+        #[arg(
+            long,
+            default_value = "1",
+            help = "Gratuitous ARP/NA rounds to send on becoming MASTER. At least 1."
+        )]
+        garp_repeat: u8,
+
+        #[arg(
+            long,
+            default_value = "200",
+            help = "Milliseconds between the gratuitous ARP/NA rounds sent on becoming MASTER."
+        )]
+        garp_interval_ms: u16,
+
+        #[arg(
+            long,
+            default_value = "0",
+            help = "Seconds between gratuitous ARP/NA refreshes while MASTER. 0 disables them."
+        )]
+        garp_refresh: u16,
+
+        #[arg(
+            long,
+            default_value = "false",
+            action = clap::ArgAction::Set,
+            help = "Send gratuitous ARPs as replies rather than requests."
+        )]
+        garp_reply: bool,
+
+        #[arg(
+            long,
+            default_value = "true",
+            action = clap::ArgAction::Set,
+            help = "When false, no mac-vlan is created: the virtual IP(s) go on the interface itself and adverts/GARPs use its own MAC."
+        )]
+        use_virtual_mac: bool,
+
+        #[arg(
+            long,
+            value_enum,
+            default_value_t = LinkBackend::Macvlan,
+            help = "Link created to carry the virtual IP(s). `ipvlan` shares the interface's MAC, for ports limited to one MAC address."
+        )]
+        link_backend: LinkBackend,
+
         #[arg(
             long = "vrrp-version",
             default_value = "3",
@@ -201,7 +489,21 @@ fn load_mode(mode: Mode) -> ConfigResult<Vec<Config>> {
             interface_name,
             priority,
             advert_interval,
+            advert_interval_cs,
             preempt_mode,
+            preempt_delay,
+            accept_mode,
+            startup_delay,
+            initial_state,
+            unicast_peers,
+            unicast_src_ip,
+            auth_password,
+            garp_repeat,
+            garp_interval_ms,
+            garp_refresh,
+            garp_reply,
+            use_virtual_mac,
+            link_backend,
             vrrp_version,
             log_file_path,
         } => {
@@ -217,7 +519,25 @@ fn load_mode(mode: Mode) -> ConfigResult<Vec<Config>> {
                 interface_name,
                 priority,
                 advert_interval,
+                advert_interval_cs,
                 preempt_mode,
+                preempt_delay,
+                accept_mode,
+                startup_delay,
+                initial_state,
+                track_interfaces: vec![],
+                track_scripts: vec![],
+                track_routes: vec![],
+                sync_group: None,
+                unicast_peers,
+                unicast_src_ip,
+                auth_password,
+                garp_repeat,
+                garp_interval_ms,
+                garp_refresh,
+                garp_reply,
+                use_virtual_mac,
+                link_backend,
                 version,
             };
             let configs = vec![config];
@@ -228,8 +548,11 @@ fn load_mode(mode: Mode) -> ConfigResult<Vec<Config>> {
 }
 
 /// Cross-instance and per-instance checks that deserialization alone can't
-/// express: name/vrid uniqueness per version, no IPv6 on v2, and
-/// advert_interval capped at 40s for v3 (12-bit centisecond wire field).
+/// express: name/vrid uniqueness per version, no IPv6 on v2,
+/// advert_interval capped at 40s for v3 (12-bit centisecond wire field),
+/// advert_interval_cs limited to whole seconds on v2, non-zero
+/// track_scripts timings, a unicast_src_ip only alongside unicast_peers,
+/// a 1-8 byte auth_password on v2 only, and a non-zero garp_repeat.
 fn validate_configs(configs: &[Config]) -> ConfigResult<()> {
     for (i, cfg) in configs.iter().enumerate() {
         let version = cfg.version;
@@ -244,8 +567,75 @@ fn validate_configs(configs: &[Config]) -> ConfigResult<()> {
             }
         }
 
+        if let Some(interval_cs) = cfg.advert_interval_cs {
+            let valid = match version {
+                VrrpVersion::V2 => {
+                    interval_cs != 0
+                        && interval_cs % 100 == 0
+                        && interval_cs / 100 <= u8::MAX as u16
+                }
+                VrrpVersion::V3 => (1..=0x0FFF).contains(&interval_cs),
+            };
+            if !valid {
+                return Err(ConfigError::InvalidAdvertIntervalCs {
+                    name: cfg.name.clone(),
+                    interval_cs,
+                    version: version.as_u8(),
+                });
+            }
+        }
+
+        for script in &cfg.track_scripts {
+            if script.interval == 0
+                || script.timeout == Some(0)
+                || script.rise == 0
+                || script.fall == 0
+            {
+                return Err(ConfigError::InvalidTrackScript {
+                    name: cfg.name.clone(),
+                    script: script.name.clone(),
+                });
+            }
+        }
+
+        if let Some(password) = &cfg.auth_password {
+            if version != VrrpVersion::V2 {
+                return Err(ConfigError::AuthPasswordNeedsV2 {
+                    name: cfg.name.clone(),
+                });
+            }
+            if password.is_empty() || password.len() > 8 {
+                return Err(ConfigError::InvalidAuthPassword {
+                    name: cfg.name.clone(),
+                });
+            }
+        }
+
+        if cfg.garp_repeat == 0 {
+            return Err(ConfigError::InvalidGarpRepeat {
+                name: cfg.name.clone(),
+            });
+        }
+
+        if cfg.unicast_src_ip.is_some() && cfg.unicast_peers.is_empty() {
+            return Err(ConfigError::UnicastSrcWithoutPeers {
+                name: cfg.name.clone(),
+            });
+        }
+
         match version {
             VrrpVersion::V2 => {
+                let v6_peer = cfg
+                    .unicast_peers
+                    .iter()
+                    .chain(&cfg.unicast_src_ip)
+                    .find(|addr| addr.is_ipv6());
+                if let Some(addr) = v6_peer {
+                    return Err(ConfigError::Ipv6NotSupportedInV2 {
+                        name: cfg.name.clone(),
+                        address: addr.to_string(),
+                    });
+                }
                 for addr in &cfg.ip_addresses {
                     if matches!(IpNet::from_str(addr), Ok(IpNet::V6(_))) {
                         return Err(ConfigError::Ipv6NotSupportedInV2 {
@@ -326,44 +716,74 @@ fn configure_logging(log_file_path: Option<String>) -> ConfigResult<()> {
 }
 
 fn read_json_config<P: AsRef<Path>>(path: P) -> ConfigResult<Vec<Config>> {
-    let path_str = path.as_ref().as_os_str();
     let path_display = path.as_ref().display().to_string();
-
-    let file =
-        File::open(path_str).map_err(|source| ConfigError::FileOpen {
-            path: path_display.clone(),
-            source,
+    let contents =
+        std::fs::read_to_string(path.as_ref()).map_err(|source| {
+            ConfigError::FileOpen {
+                path: path_display.clone(),
+                source,
+            }
         })?;
 
-    let reader = BufReader::new(file);
+    parse_json_config(&contents, &path_display)
+}
 
-    let list_file_configs: Vec<Config> = match serde_json::from_reader(reader) {
-        Ok(config) => config,
-        Err(_) => single_file_config(path_str, &path_display)?,
+/// Parses any of the three config file forms: an array of instances, a
+/// single instance object, or an object with an `instances` array and a
+/// `sync_groups` section. `path` is only used in errors.
+fn parse_json_config(contents: &str, path: &str) -> ConfigResult<Vec<Config>> {
+    let parse_err = |source| ConfigError::Parse {
+        path: path.to_string(),
+        source,
     };
 
-    Ok(list_file_configs)
-}
+    if let Ok(configs) = serde_json::from_str::<Vec<Config>>(contents) {
+        return Ok(configs);
+    }
 
-fn single_file_config(
-    path: &OsStr,
-    path_display: &str,
-) -> ConfigResult<Vec<Config>> {
-    // Called only after the file failed to parse as a config array; re-reads
-    // it as a single config object instead.
-    let file = File::open(path).map_err(|source| ConfigError::FileOpen {
-        path: path_display.to_string(),
-        source,
-    })?;
+    let value: serde_json::Value =
+        serde_json::from_str(contents).map_err(parse_err)?;
+    if value.get("instances").is_none() {
+        let config: Config =
+            serde_json::from_value(value).map_err(parse_err)?;
+        return Ok(vec![config]);
+    }
 
-    let reader = BufReader::new(file);
-    match serde_json::from_reader(reader) {
-        Ok(config) => Ok(vec![config]),
-        Err(source) => Err(ConfigError::Parse {
-            path: path_display.to_string(),
-            source,
-        }),
+    let SectionedConfig {
+        mut instances,
+        sync_groups,
+    } = serde_json::from_value(value).map_err(parse_err)?;
+    assign_sync_groups(&mut instances, &sync_groups)?;
+    Ok(instances)
+}
+
+/// Points each sync group member's `sync_group` at its group. Every member
+/// has to name exactly one configured instance, and no instance can be in
+/// more than one group.
+fn assign_sync_groups(
+    configs: &mut [Config],
+    groups: &[SyncGroup],
+) -> ConfigResult<()> {
+    for group in groups {
+        for instance in &group.instances {
+            let mut members =
+                configs.iter_mut().filter(|cfg| &cfg.name == instance);
+            let Some(member) = members.next() else {
+                return Err(ConfigError::UnknownSyncGroupMember {
+                    group: group.name.clone(),
+                    instance: instance.clone(),
+                });
+            };
+            if members.next().is_some() || member.sync_group.is_some() {
+                return Err(ConfigError::SyncGroupMemberConflict {
+                    group: group.name.clone(),
+                    instance: instance.clone(),
+                });
+            }
+            member.sync_group = Some(group.name.clone());
+        }
     }
+    Ok(())
 }
 
 #[cfg(test)]
@@ -378,11 +798,93 @@ mod tests {
             name: name.to_string(),
             priority: 100,
             advert_interval: 1,
+            advert_interval_cs: None,
             preempt_mode: true,
+            preempt_delay: 0,
+            accept_mode: true,
+            startup_delay: 0,
+            initial_state: InitialState::Auto,
+            track_interfaces: vec![],
+            track_scripts: vec![],
+            track_routes: vec![],
+            sync_group: None,
+            unicast_peers: vec![],
+            unicast_src_ip: None,
+            auth_password: None,
+            garp_repeat: 1,
+            garp_interval_ms: 200,
+            garp_refresh: 0,
+            garp_reply: false,
+            use_virtual_mac: true,
+            link_backend: LinkBackend::Macvlan,
             version,
         }
     }
 
+    const SYNC_GROUP_CONFIG: &str = r#"{
+        "instances": [
+            { "name": "WAN", "vrid": 51, "interface_name": "eth0",
+              "ip_addresses": ["192.168.100.10/24"] },
+            { "name": "LAN", "vrid": 52, "interface_name": "eth1",
+              "ip_addresses": ["10.0.0.1/24"] },
+            { "name": "MGMT", "vrid": 53, "interface_name": "eth2",
+              "ip_addresses": ["10.1.0.1/24"] }
+        ],
+        "sync_groups": [{ "name": "nat", "instances": ["WAN", "LAN"] }]
+    }"#;
+
+    #[test]
+    fn sync_groups_are_assigned_to_their_members() {
+        let configs = parse_json_config(SYNC_GROUP_CONFIG, "test").unwrap();
+
+        let group_of = |name: &str| {
+            let cfg = configs.iter().find(|cfg| cfg.name == name).unwrap();
+            cfg.sync_group.clone()
+        };
+        assert_eq!(group_of("WAN").as_deref(), Some("nat"));
+        assert_eq!(group_of("LAN").as_deref(), Some("nat"));
+        assert_eq!(group_of("MGMT"), None);
+    }
+
+    #[test]
+    fn plain_array_and_single_object_files_still_parse() {
+        let single = r#"{
+            "vrid": 51,
+            "ip_addresses": ["192.168.100.10/24"],
+            "interface_name": "eth0"
+        }"#;
+        assert_eq!(parse_json_config(single, "test").unwrap().len(), 1);
+        assert_eq!(
+            parse_json_config(&format!("[{single}, {single}]"), "test")
+                .unwrap()
+                .len(),
+            2
+        );
+    }
+
+    #[test]
+    fn sync_group_members_must_be_configured_instances() {
+        let json = SYNC_GROUP_CONFIG.replace(r#""LAN"]"#, r#""DMZ"]"#);
+        assert!(matches!(
+            parse_json_config(&json, "test"),
+            Err(ConfigError::UnknownSyncGroupMember { instance, .. })
+                if instance == "DMZ"
+        ));
+    }
+
+    #[test]
+    fn instances_belong_to_at_most_one_sync_group() {
+        let json = SYNC_GROUP_CONFIG.replace(
+            r#"["WAN", "LAN"] }]"#,
+            r#"["WAN", "LAN"] }, { "name": "b", "instances": ["LAN"] }]"#,
+        );
+        assert!(matches!(
+            parse_json_config(&json, "test"),
+            Err(ConfigError::SyncGroupMemberConflict { instance, .. })
+                if instance == "LAN"
+        ));
+    }
+
     #[test]
     fn version_defaults_to_v3_when_omitted() {
         let json = r#"{
@@ -394,6 +896,205 @@ mod tests {
         assert_eq!(cfg.version, VrrpVersion::V3);
     }
 
+    #[test]
+    fn accept_mode_defaults_to_true_when_omitted() {
+        let json = r#"{
+            "vrid": 51,
+            "ip_addresses": ["192.168.100.10/24"],
+            "interface_name": "eth0"
+        }"#;
+        let cfg: Config = serde_json::from_str(json).unwrap();
+        assert!(cfg.accept_mode);
+    }
+
+    #[test]
+    fn use_virtual_mac_defaults_to_true_when_omitted() {
+        let json = r#"{
+            "vrid": 51,
+            "ip_addresses": ["192.168.100.10/24"],
+            "interface_name": "eth0"
+        }"#;
+        let cfg: Config = serde_json::from_str(json).unwrap();
+        assert!(cfg.use_virtual_mac);
+        assert_eq!(cfg.link_backend, LinkBackend::Macvlan);
+    }
+
+    #[test]
+    fn link_backend_parses_lowercase_names() {
+        let json = r#"{
+            "vrid": 51,
+            "ip_addresses": ["192.168.100.10/24"],
+            "interface_name": "eth0",
+            "link_backend": "ipvlan"
+        }"#;
+        let cfg: Config = serde_json::from_str(json).unwrap();
+        assert_eq!(cfg.link_backend, LinkBackend::Ipvlan);
+    }
+
+    #[test]
+    fn initial_state_parses_lowercase_names() {
+        let json = r#"{
+            "vrid": 51,
+            "ip_addresses": ["192.168.100.10/24"],
+            "interface_name": "eth0",
+            "initial_state": "backup"
+        }"#;
+        let cfg: Config = serde_json::from_str(json).unwrap();
+        assert_eq!(cfg.initial_state, InitialState::Backup);
+        assert_eq!(cfg.startup_delay, 0);
+    }
+
+    #[test]
+    fn track_interfaces_weight_is_optional() {
+        let json = r#"{
+            "vrid": 51,
+            "ip_addresses": ["192.168.100.10/24"],
+            "interface_name": "eth0",
+            "track_interfaces": [
+                { "name": "eth1", "weight": 20 },
+                { "name": "eth2" }
+            ]
+        }"#;
+        let cfg: Config = serde_json::from_str(json).unwrap();
+        assert_eq!(
+            cfg.track_interfaces,
+            vec![
+                TrackInterface {
+                    name: "eth1".to_string(),
+                    weight: Some(20)
+                },
+                TrackInterface {
+                    name: "eth2".to_string(),
+                    weight: None
+                },
+            ]
+        );
+    }
+
+    #[test]
+    fn track_scripts_fill_in_default_timings() {
+        let json = r#"{
+            "vrid": 51,
+            "ip_addresses": ["192.168.100.10/24"],
+            "interface_name": "eth0",
+            "track_scripts": [
+                { "name": "haproxy", "command": "pidof haproxy", "weight": 50 }
+            ]
+        }"#;
+        let cfg: Config = serde_json::from_str(json).unwrap();
+        let script = &cfg.track_scripts[0];
+        assert_eq!(script.interval, 2);
+        assert_eq!(script.timeout, None);
+        assert_eq!((script.rise, script.fall), (1, 1));
+        assert_eq!(script.weight, Some(50));
+    }
+
+    #[test]
+    fn track_script_with_zero_fall_is_rejected() {
+        let mut cfg = sample("VR_1", 51, VrrpVersion::V3);
+        cfg.track_scripts.push(TrackScript {
+            name: "haproxy".to_string(),
+            command: "pidof haproxy".to_string(),
+            interval: 2,
+            timeout: None,
+            rise: 1,
+            fall: 0,
+            weight: None,
+        });
+
+        assert!(matches!(
+            validate_configs(&[cfg]),
+            Err(ConfigError::InvalidTrackScript { .. })
+        ));
+    }
+
+    #[test]
+    fn track_routes_default_to_the_main_table() {
+        let json = r#"{
+            "vrid": 51,
+            "ip_addresses": ["192.168.100.10/24"],
+            "interface_name": "eth0",
+            "track_routes": [
+                { "prefix": "0.0.0.0/0" },
+                { "prefix": "2001:db8::/32", "table": 100, "weight": 30 }
+            ]
+        }"#;
+        let cfg: Config = serde_json::from_str(json).unwrap();
+        assert_eq!(cfg.track_routes[0].prefix, "0.0.0.0/0".parse().unwrap());
+        assert_eq!(cfg.track_routes[0].table, 254);
+        assert_eq!(cfg.track_routes[0].weight, None);
+        assert_eq!(cfg.track_routes[1].table, 100);
+    }
+
+    #[test]
+    fn track_route_prefix_must_be_a_valid_network() {
+        let json = r#"{
+            "vrid": 51,
+            "ip_addresses": ["192.168.100.10/24"],
+            "interface_name": "eth0",
+            "track_routes": [{ "prefix": "default" }]
+        }"#;
+        assert!(serde_json::from_str::<Config>(json).is_err());
+    }
+
+    #[test]
+    fn unicast_src_ip_needs_unicast_peers() {
+        let mut cfg = sample("VR_1", 51, VrrpVersion::V3);
+        cfg.unicast_src_ip = Some("10.0.0.1".parse().unwrap());
+        assert!(matches!(
+            validate_configs(&[cfg.clone()]),
+            Err(ConfigError::UnicastSrcWithoutPeers { .. })
+        ));
+
+        cfg.unicast_peers = vec!["10.0.0.2".parse().unwrap()];
+        assert!(validate_configs(&[cfg]).is_ok());
+    }
+
+    #[test]
+    fn v2_rejects_ipv6_unicast_peers() {
+        let mut cfg = sample("VR_1", 51, VrrpVersion::V2);
+        cfg.unicast_peers = vec!["fd00::2".parse().unwrap()];
+        assert!(matches!(
+            validate_configs(&[cfg]),
+            Err(ConfigError::Ipv6NotSupportedInV2 { .. })
+        ));
+    }
+
+    #[test]
+    fn auth_password_is_v2_only() {
+        let mut cfg = sample("VR_1", 51, VrrpVersion::V3);
+        cfg.auth_password = Some("secret".to_string());
+        assert!(matches!(
+            validate_configs(&[cfg.clone()]),
+            Err(ConfigError::AuthPasswordNeedsV2 { .. })
+        ));
+
+        cfg.version = VrrpVersion::V2;
+        assert!(validate_configs(&[cfg]).is_ok());
+    }
+
+    #[test]
+    fn auth_password_fits_in_8_bytes() {
+        let mut cfg = sample("VR_1", 51, VrrpVersion::V2);
+        for password in ["", "123456789"] {
+            cfg.auth_password = Some(password.to_string());
+            assert!(matches!(
+                validate_configs(&[cfg.clone()]),
+                Err(ConfigError::InvalidAuthPassword { .. })
+            ));
+        }
+    }
+
+    #[test]
+    fn garp_repeat_must_send_at_least_once() {
+        let mut cfg = sample("VR_1", 51, VrrpVersion::V3);
+        cfg.garp_repeat = 0;
+        assert!(matches!(
+            validate_configs(&[cfg]),
+            Err(ConfigError::InvalidGarpRepeat { .. })
+        ));
+    }
+
     #[test]
     fn version_rejects_values_other_than_2_or_3() {
         let json = r#"{
@@ -474,4 +1175,44 @@ mod tests {
 
         assert!(validate_configs(&[cfg]).is_ok());
     }
+
+    #[test]
+    fn sub_second_advert_interval_cs_allowed_for_v3() {
+        let mut cfg = sample("VR_1", 51, VrrpVersion::V3);
+        cfg.advert_interval_cs = Some(10);
+
+        assert!(validate_configs(&[cfg]).is_ok());
+    }
+
+    #[test]
+    fn advert_interval_cs_outside_12_bits_rejected_for_v3() {
+        for interval_cs in [0, 0x1000] {
+            let mut cfg = sample("VR_1", 51, VrrpVersion::V3);
+            cfg.advert_interval_cs = Some(interval_cs);
+
+            assert!(matches!(
+                validate_configs(&[cfg]),
+                Err(ConfigError::InvalidAdvertIntervalCs { .. })
+            ));
+        }
+    }
+
+    #[test]
+    fn sub_second_advert_interval_cs_rejected_for_v2() {
+        let mut cfg = sample("VR_1", 51, VrrpVersion::V2);
+        cfg.advert_interval_cs = Some(150);
+
+        assert!(matches!(
+            validate_configs(&[cfg]),
+            Err(ConfigError::InvalidAdvertIntervalCs { .. })
+        ));
+    }
+
+    #[test]
+    fn whole_second_advert_interval_cs_allowed_for_v2() {
+        let mut cfg = sample("VR_1", 51, VrrpVersion::V2);
+        cfg.advert_interval_cs = Some(300);
+
+        assert!(validate_configs(&[cfg]).is_ok());
+    }
 }
@@ -1,230 +1,380 @@
-use std::net::{IpAddr, Ipv4Addr, Ipv6Addr};
-use std::sync::Arc;
-use std::time::{Duration, Instant};
+use std::net::IpAddr;
+use std::process::Stdio;
+use std::time::Duration;
 
-use pnet::packet::ethernet::EthernetPacket;
-use pnet::packet::ipv4::Ipv4Packet;
+use futures_util::{StreamExt, TryStreamExt};
+use netlink_packet_core::NetlinkPayload;
+use netlink_packet_route::RouteNetlinkMessage;
+use rtnetlink::{MulticastGroup, new_multicast_connection};
+use tokio::process::Command;
+use tokio::sync::{broadcast, mpsc, oneshot};
+use tokio::task::JoinSet;
 use tokio::time;
 
-use crate::NetResult;
+use crate::actor::{Input, TrackUpdate};
+use crate::dispatch::{self, Filter, ReceiverKind};
 use crate::error::NetworkError;
-use crate::network::{ArpListener, NdpListener, VrrpListener};
-use crate::observer::EventObserver;
-use crate::pkt::handlers::{
-    handle_incoming_arp_pkt, handle_incoming_ndp_pkt,
-    handle_incoming_vrrp_v4_pkt, handle_incoming_vrrp_v6_pkt,
-};
-use crate::state_machine::Event;
-
-/// Listens for VRRP advertisements on a raw IP socket bound to the VRRP
-/// multicast group and hands each one off to the VRRP packet handler.
-pub(crate) async fn vrrp_process(items: crate::TaskItems) -> NetResult<()> {
-    let unspec_addr = IpAddr::V4(Ipv4Addr::UNSPECIFIED);
-    let listener =
-        VrrpListener::bind(&items.parent_interface.name, unspec_addr).map_err(
-            |source| NetworkError::SocketBind {
-                kind: "socket4",
-                iface: items.parent_interface.name.clone(),
-                source,
-            },
-        )?;
-
-    let vrouter = items.vrouter;
-
-    loop {
-        let (buf, _) = match listener.recv(unspec_addr).await {
-            Ok(buf) => buf,
-            Err(err) => {
-                log::warn!("Error receiving VRRP packet: {err}");
-                continue;
-            }
-        };
+use crate::state_machine::{Event, State};
+use crate::track::ScriptHealth;
+use crate::{NetResult, TaskItems, announce, general, network};
 
-        let ip_packet = match Ipv4Packet::new(&buf) {
-            Some(pkt) => pkt,
-            None => {
-                log::warn!("Unable to read incoming IP packet");
-                continue;
-            }
-        };
+/// Holds this instance's registration with the shared `kind` receiver on
+/// `ifname` (see `dispatch`) until the task is aborted.
+async fn listen(
+    kind: ReceiverKind,
+    ifname: &str,
+    items: &TaskItems,
+) -> NetResult<()> {
+    let settings = &items.settings;
+    let filter = Filter {
+        vrid: settings.vrid,
+        peers: settings.unicast_peers.clone(),
+        vips: (settings.ipv4_addrs().into_iter().map(IpAddr::V4))
+            .chain(settings.ipv6_addrs().into_iter().map(IpAddr::V6))
+            .collect(),
+    };
+    let _subscription =
+        dispatch::subscribe(kind, ifname, filter, items.router.clone())
+            .map_err(|source| NetworkError::SocketBind {
+                kind: kind.label(),
+                iface: ifname.to_string(),
+                source,
+            })?;
+    std::future::pending().await
+}
 
-        if let Err(err) =
-            handle_incoming_vrrp_v4_pkt(&ip_packet, Arc::clone(&vrouter))
-        {
-            log::warn!("problem handling incoming VRRP packet");
-            log::warn!("{err}");
-        }
-    }
+/// Receives this instance's VRRP advertisements from the IPv4 socket shared
+/// by every instance on the parent interface: those for its VRID, and only
+/// its peers' when peering by unicast.
+pub(crate) async fn vrrp_process(items: TaskItems) -> NetResult<()> {
+    listen(ReceiverKind::Vrrp4, &items.parent_interface.name, &items).await
 }
 
 /// IPv6 counterpart of `vrrp_process`. Only ever spawned for a v3 instance
 /// (see `TaskItems::interface_v6`); returns immediately if there's no v6
-/// mac-vlan to listen on.
-pub(crate) async fn vrrp_process_v6(items: crate::TaskItems) -> NetResult<()> {
+/// side.
+pub(crate) async fn vrrp_process_v6(items: TaskItems) -> NetResult<()> {
+    if items.interface_v6.is_none() {
+        return Ok(());
+    }
+    listen(ReceiverKind::Vrrp6, &items.parent_interface.name, &items).await
+}
+
+/// Receives ARP frames from the AF_PACKET socket shared by every instance
+/// on the parent interface -- which sees requests whichever of its
+/// mac-vlans they're delivered to.
+pub(crate) async fn arp_process(items: TaskItems) -> NetResult<()> {
+    listen(ReceiverKind::Arp, &items.parent_interface.name, &items).await
+}
+
+/// IPv6's equivalent of `arp_process`: receives Neighbor Solicitations on
+/// the v6 mac-vlan. Only ever spawned for a v3 instance.
+pub(crate) async fn ndp_process(items: TaskItems) -> NetResult<()> {
     let Some(interface_v6) = items.interface_v6.clone() else {
         return Ok(());
     };
+    listen(ReceiverKind::Ndp, &interface_v6.name, &items).await
+}
 
-    let unspec_addr = IpAddr::V6(Ipv6Addr::UNSPECIFIED);
-    let listener =
-        VrrpListener::bind(&items.parent_interface.name, unspec_addr).map_err(
-            |source| NetworkError::SocketBind {
-                kind: "socket6",
-                iface: items.parent_interface.name.clone(),
-                source,
-            },
-        )?;
+/// Feeds the carrier state of every `track_interfaces` entry into the
+/// router's tracked objects, from an initial link dump and then rtnetlink
+/// link notifications. A tracked interface that doesn't exist counts as
+/// down. Fires `ready` once the dump is in; returns straight away (dropping
+/// it) if nothing is tracked.
+pub(crate) async fn track_interfaces_process(
+    items: TaskItems,
+    ready: oneshot::Sender<()>,
+) -> NetResult<()> {
+    let tracked = &items.settings.track_interfaces;
+    if tracked.is_empty() {
+        return Ok(());
+    }
 
-    let vrouter = items.vrouter;
-    let _ = interface_v6;
+    let (connection, handle, mut messages) =
+        new_multicast_connection(&[MulticastGroup::Link])
+            .map_err(NetworkError::NetlinkConnect)?;
+    tokio::spawn(connection);
 
+    let update = async |name: &str, up: bool| {
+        let Some(track) = tracked.iter().find(|t| t.name == name) else {
+            return;
+        };
+        let update = TrackUpdate {
+            key: format!("interface {name}"),
+            weight: track.weight,
+            healthy: up,
+        };
+        items.router.send(Input::Tracked(vec![update])).await;
+    };
+
+    let mut seen = vec![];
+    let mut links = handle.link().get().execute();
     loop {
-        let (buf, src) = match listener.recv(unspec_addr).await {
-            Ok(pair) => pair,
+        match links.try_next().await {
+            Ok(Some(link)) => {
+                if let Some((name, up)) = general::link_carrier(&link) {
+                    seen.push(name.to_string());
+                    update(name, up).await;
+                }
+            }
+            Ok(None) => break,
             Err(err) => {
-                log::warn!("Error receiving VRRPv6 packet: {err}");
-                continue;
+                log::warn!(
+                    "Problem dumping links for interface tracking: {err}"
+                );
+                break;
             }
-        };
+        }
+    }
+    for track in tracked.iter().filter(|t| !seen.contains(&t.name)) {
+        update(&track.name, false).await;
+    }
+    let _ = ready.send(());
 
-        if let IpAddr::V6(src) = src
-            && let Err(err) =
-                handle_incoming_vrrp_v6_pkt(&buf, src, Arc::clone(&vrouter))
-        {
-            log::warn!("problem handling incoming VRRPv6 packet");
-            log::warn!("{err}");
+    while let Some((message, _)) = messages.next().await {
+        let NetlinkPayload::InnerMessage(message) = message.payload else {
+            continue;
+        };
+        match message {
+            RouteNetlinkMessage::NewLink(link) => {
+                if let Some((name, up)) = general::link_carrier(&link) {
+                    update(name, up).await;
+                }
+            }
+            RouteNetlinkMessage::DelLink(link) => {
+                if let Some((name, _)) = general::link_carrier(&link) {
+                    update(name, false).await;
+                }
+            }
+            _ => {}
         }
     }
+    Ok(())
 }
 
-/// Listens for ARP frames on a raw AF_PACKET socket bound to this
-/// interface and hands each one off to the ARP packet handler.
-pub(crate) async fn arp_process(items: crate::TaskItems) -> NetResult<()> {
-    let listener =
-        ArpListener::bind(&items.interface.name).map_err(|source| {
-            NetworkError::SocketBind {
-                kind: "ARP",
-                iface: items.interface.name.clone(),
-                source,
-            }
-        })?;
-    let vrouter = items.vrouter;
+/// Lowers the priority (or faults the router) while any `track_routes`
+/// prefix is missing from its kernel routing table. Re-checks every tracked
+/// route against a fresh route dump whenever rtnetlink reports a change to
+/// one of them. Fires `ready` after the first check; returns straight away
+/// (dropping it) if nothing is tracked.
+pub(crate) async fn track_routes_process(
+    items: TaskItems,
+    ready: oneshot::Sender<()>,
+) -> NetResult<()> {
+    let tracked = &items.settings.track_routes;
+    if tracked.is_empty() {
+        return Ok(());
+    }
 
-    loop {
-        let buf = match listener.recv().await {
-            Ok(buf) => buf,
+    let (connection, handle, mut messages) = new_multicast_connection(&[
+        MulticastGroup::Ipv4Route,
+        MulticastGroup::Ipv6Route,
+    ])
+    .map_err(NetworkError::NetlinkConnect)?;
+    tokio::spawn(connection);
+
+    let refresh = async || {
+        let installed = match general::installed_routes(&handle).await {
+            Ok(installed) => installed,
             Err(err) => {
-                log::warn!("Error receiving ARP packet: {err}");
-                continue;
+                log::warn!("Problem dumping routes for route tracking: {err}");
+                return;
             }
         };
+        let updates = tracked
+            .iter()
+            .map(|track| TrackUpdate {
+                key: format!("route {} (table {})", track.prefix, track.table),
+                weight: track.weight,
+                healthy: installed
+                    .contains(&(track.prefix.trunc(), track.table)),
+            })
+            .collect();
+        items.router.send(Input::Tracked(updates)).await;
+    };
 
-        let eth_packet = match EthernetPacket::new(&buf) {
-            Some(pkt) => pkt,
-            None => continue,
+    refresh().await;
+    let _ = ready.send(());
+    while let Some((message, _)) = messages.next().await {
+        let NetlinkPayload::InnerMessage(
+            RouteNetlinkMessage::NewRoute(route)
+            | RouteNetlinkMessage::DelRoute(route),
+        ) = message.payload
+        else {
+            continue;
         };
-
-        if let Err(err) =
-            handle_incoming_arp_pkt(&eth_packet, Arc::clone(&vrouter))
+        let Some(changed) = general::route_prefix(&route) else {
+            continue;
+        };
+        if tracked
+            .iter()
+            .any(|t| (t.prefix.trunc(), t.table) == changed)
         {
-            log::error!("problem handling incoming ARP packet");
-            log::error!("{err}");
+            refresh().await;
         }
     }
+    Ok(())
 }
 
-/// IPv6's equivalent of `arp_process`: listens for Neighbor
-/// Solicitation/Advertisement traffic on the v6 mac-vlan. Only ever
-/// spawned for a v3 instance.
-pub(crate) async fn ndp_process(items: crate::TaskItems) -> NetResult<()> {
-    let Some(interface_v6) = items.interface_v6.clone() else {
+/// Follows the transitions the other members of this instance's sync group
+/// publish. Returns straight away if it isn't in one.
+pub(crate) async fn sync_group_process(items: TaskItems) -> NetResult<()> {
+    let name = &items.settings.name;
+    let Some(group) = &items.settings.sync_group else {
         return Ok(());
     };
 
-    let listener = NdpListener::bind(&interface_v6.name).map_err(|source| {
-        NetworkError::SocketBind {
-            kind: "NDP",
-            iface: interface_v6.name.clone(),
-            source,
-        }
-    })?;
-    let vrouter = items.vrouter;
-
+    let mut messages = group.subscribe();
     loop {
-        let (payload, src) = match listener.recv().await {
-            Ok(pair) => pair,
-            Err(err) => {
-                log::warn!("Error receiving NDP packet: {err}");
+        let message = match messages.recv().await {
+            Ok(message) => message,
+            Err(broadcast::error::RecvError::Lagged(skipped)) => {
+                log::warn!(
+                    "({name}) missed {skipped} transition(s) from sync group {}",
+                    group.name
+                );
                 continue;
             }
+            Err(broadcast::error::RecvError::Closed) => return Ok(()),
         };
-
-        if let Err(err) =
-            handle_incoming_ndp_pkt(&payload, src, Arc::clone(&vrouter))
-        {
-            log::error!("problem handling incoming NDP packet");
-            log::error!("{err}");
+        if message.from == *name {
+            continue;
         }
+        let event = Event::Sync(message.state);
+        items.router.send(Input::Event(event)).await;
     }
 }
 
-/// Used to track the various timers: (MasterDownTimer and Advertimer)
-/// Has been explained in RFC 3768 section 6.2
-pub(crate) async fn timer_process(items: crate::TaskItems) -> NetResult<()> {
-    let mut interval = time::interval(Duration::from_millis(1000));
-    let vrouter = items.vrouter;
+/// Announces the virtual IP(s) -- gratuitous ARPs and unsolicited NAs --
+/// each time this instance becomes MASTER: `garp_repeat` rounds
+/// `garp_interval_ms` apart, then a refresh round every `garp_refresh`
+/// seconds for as long as it stays MASTER. Becoming MASTER again part-way
+/// through restarts the sequence. Every frame goes through the process-wide
+/// rate cap in `announce`.
+pub(crate) async fn announce_process(items: TaskItems) -> NetResult<()> {
+    let settings = &items.settings;
+    let garp = settings.garp;
+    let v4_mac = items.interface.mac.map(|mac| mac.octets());
+    let v6_mac = items
+        .interface_v6
+        .as_ref()
+        .and_then(|iface| iface.mac)
+        .map(|mac| mac.octets());
 
     loop {
-        interval.tick().await;
-        let mut vrouter = match vrouter.lock() {
-            Ok(vrouter) => vrouter,
-            Err(_) => {
-                log::error!("Unable to get mutex for vrouter");
-                continue;
+        settings.announce.notified().await;
+        let mut rounds: u8 = 0;
+        loop {
+            // The first round goes out as part of becoming MASTER; later
+            // ones only while it's still MASTER.
+            if rounds > 0 && items.router.state() != State::Master {
+                break;
             }
-        };
-        let timer = vrouter.fsm.timer;
-
-        match timer.t_type {
-            crate::state_machine::TimerType::MasterDown => {
-                match vrouter.fsm.timer.waiting_for {
-                    // waiting is the time being waited for
-                    // to notify for the master down
-                    Some(waiting) => {
-                        if Instant::now() > waiting {
-                            match EventObserver::notify_mut(
-                                vrouter,
-                                Event::MasterDown,
-                            ) {
-                                Ok(info) => info,
-                                Err(err) => return Err(err),
-                            }
-                        }
-                    }
-                    None => {
-                        log::warn!("No timer being waited for.");
-                        continue;
-                    }
-                };
+            let arps =
+                v4_mac.map_or(vec![], |mac| settings.gratuitous_arps(mac));
+            for arp in arps {
+                announce::acquire().await;
+                network::send_packet_arp(&settings.mac_vlan_interface_v4, arp);
+            }
+            if let Some(v6_iface) = &settings.mac_vlan_interface_v6 {
+                let nas = v6_mac.map_or(vec![], |mac| {
+                    settings.neighbor_advertisements(mac)
+                });
+                for na in nas {
+                    announce::acquire().await;
+                    let target = na.target_address;
+                    network::send_neighbor_advertisement(v6_iface, target, na);
+                }
+            }
+
+            rounds = rounds.saturating_add(1);
+            let wait = if rounds < garp.repeat {
+                garp.interval()
+            } else if let Some(refresh) = garp.refresh() {
+                refresh
+            } else {
+                break;
+            };
+            tokio::select! {
+                _ = settings.announce.notified() => rounds = 0,
+                _ = time::sleep(wait) => {}
             }
+        }
+    }
+}
+
+/// Runs every `track_scripts` entry on its own interval and feeds its
+/// rise/fall-debounced result into the router's tracked objects. Fires
+/// `ready` once every script has a first result; returns straight away
+/// (dropping it) if nothing is tracked.
+pub(crate) async fn track_scripts_process(
+    items: TaskItems,
+    ready: oneshot::Sender<()>,
+) -> NetResult<()> {
+    // Each check holds a sender until its first result is in, so the
+    // channel closes once they all have (or died trying).
+    let (first_tx, mut first_rx) = mpsc::channel::<()>(1);
+    let mut checks = JoinSet::new();
+    for script in items.settings.track_scripts.clone() {
+        let router = items.router.clone();
+        let mut first = Some(first_tx.clone());
+        checks.spawn(async move {
+            let mut health = ScriptHealth::new(script.rise, script.fall);
+            let timeout = script.timeout.unwrap_or(script.interval);
+            let mut interval =
+                time::interval(Duration::from_secs(script.interval as u64));
+            interval.set_missed_tick_behavior(time::MissedTickBehavior::Delay);
+            let key = format!("script {}", script.name);
 
-            crate::state_machine::TimerType::Adver => {
-                match vrouter.fsm.timer.waiting_for {
-                    Some(waiting) => {
-                        if Instant::now() > waiting {
-                            vrouter.send_advertisement();
-                            let advert_time = vrouter.advert_interval as f32;
-                            vrouter.fsm.set_advert_timer(advert_time);
-                        }
-                    }
-                    None => {
-                        log::warn!("No timer being waited for.");
-                        continue;
-                    }
+            loop {
+                interval.tick().await;
+                let success = run_track_script(&script.command, timeout).await;
+                let Some(healthy) = health.record(success) else {
+                    continue;
                 };
+
+                let update = TrackUpdate {
+                    key: key.clone(),
+                    weight: script.weight,
+                    healthy,
+                };
+                router.send(Input::Tracked(vec![update])).await;
+                // The first result always gets this far.
+                drop(first.take());
             }
+        });
+    }
+    if checks.is_empty() {
+        return Ok(());
+    }
 
-            crate::state_machine::TimerType::Null => {}
+    drop(first_tx);
+    let _ = first_rx.recv().await;
+    let _ = ready.send(());
+    while checks.join_next().await.is_some() {}
+    Ok(())
+}
+
+/// Runs `command` through `/bin/sh -c`; `true` if it exits 0 within
+/// `timeout` seconds. A run that overruns is killed.
+async fn run_track_script(command: &str, timeout: u16) -> bool {
+    let child = Command::new("/bin/sh")
+        .args(["-c", command])
+        .stdin(Stdio::null())
+        .stdout(Stdio::null())
+        .stderr(Stdio::null())
+        .kill_on_drop(true)
+        .status();
+
+    match time::timeout(Duration::from_secs(timeout as u64), child).await {
+        Ok(Ok(status)) => status.success(),
+        Ok(Err(err)) => {
+            log::warn!("Unable to run track script {command:?}: {err}");
+            false
+        }
+        Err(_) => {
+            log::warn!("Track script {command:?} timed out after {timeout}s");
+            false
         }
     }
 }
@@ -0,0 +1,439 @@
+//! Receive sockets shared by every instance in the process: one VRRP
+//! listener per interface and address family, and one ARP and one NDP
+//! listener per interface, however many instances run on it. Each packet
+//! is decoded once and queued for the instance(s) it's for -- by VRID (and
+//! unicast peer) for adverts, by the virtual address asked about for
+//! ARP/NDP.
+use std::collections::HashMap;
+use std::io;
+use std::net::{IpAddr, Ipv4Addr, Ipv6Addr};
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, LazyLock, Mutex, MutexGuard, PoisonError};
+
+use pnet::packet::Packet;
+use pnet::packet::ethernet::{EtherTypes, EthernetPacket};
+use pnet::packet::ipv4::Ipv4Packet;
+use tokio::task::JoinHandle;
+
+use crate::actor::{Input, RouterHandle};
+use crate::error::PacketError;
+use crate::links;
+use crate::network::{ArpListener, NdpListener, VrrpListener};
+use crate::packet::{ArpPacket, NdpNeighborSolicitation, VrrpPacket};
+use crate::pkt::handlers::log_drop;
+
+static RECEIVERS: LazyLock<Mutex<HashMap<Key, Receiver>>> =
+    LazyLock::new(|| Mutex::new(HashMap::new()));
+static NEXT_MEMBER_ID: AtomicU64 = AtomicU64::new(0);
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub(crate) enum ReceiverKind {
+    Vrrp4,
+    Vrrp6,
+    Arp,
+    Ndp,
+}
+
+impl ReceiverKind {
+    /// As reported in `NetworkError::SocketBind`.
+    pub(crate) fn label(self) -> &'static str {
+        match self {
+            Self::Vrrp4 => "socket4",
+            Self::Vrrp6 => "socket6",
+            Self::Arp => "ARP",
+            Self::Ndp => "NDP",
+        }
+    }
+}
+
+/// Which packets an instance is handed: adverts for its VRID and, when it
+/// peers by unicast, only those sent by one of its peers; ARP requests and
+/// Neighbor Solicitations for one of its `vips`.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub(crate) struct Filter {
+    pub(crate) vrid: u8,
+    pub(crate) peers: Vec<IpAddr>,
+    pub(crate) vips: Vec<IpAddr>,
+}
+
+impl Filter {
+    fn accepts_source(&self, src: IpAddr) -> bool {
+        self.peers.is_empty() || self.peers.contains(&src)
+    }
+}
+
+struct Member {
+    id: u64,
+    filter: Filter,
+    router: RouterHandle,
+}
+
+/// The instances registered with one receiver.
+#[derive(Default)]
+struct MemberSet {
+    members: Vec<Member>,
+    /// Which member ARP/NDP traffic for each virtual address goes to.
+    by_vip: HashMap<IpAddr, RouterHandle>,
+}
+
+impl MemberSet {
+    fn insert(&mut self, member: Member) {
+        for vip in &member.filter.vips {
+            self.by_vip.insert(*vip, member.router.clone());
+        }
+        self.members.push(member);
+    }
+
+    fn remove(&mut self, id: u64) {
+        self.members.retain(|member| member.id != id);
+        // Rebuilt rather than pruned, so an address another member also
+        // claims stays routed to it.
+        self.by_vip = self
+            .members
+            .iter()
+            .flat_map(|member| {
+                member
+                    .filter
+                    .vips
+                    .iter()
+                    .map(|vip| (*vip, member.router.clone()))
+            })
+            .collect();
+    }
+
+    /// The member that answers for `vip`, if any.
+    fn for_vip(&self, vip: IpAddr) -> Option<RouterHandle> {
+        self.by_vip.get(&vip).cloned()
+    }
+}
+
+type Members = Arc<Mutex<MemberSet>>;
+type Key = (String, ReceiverKind);
+
+struct Receiver {
+    members: Members,
+    task: JoinHandle<()>,
+}
+
+/// An instance's registration with a shared receiver. Dropping it
+/// unregisters the instance; the last one out closes the socket.
+pub(crate) struct Subscription {
+    key: Key,
+    id: u64,
+}
+
+impl Drop for Subscription {
+    fn drop(&mut self) {
+        let mut receivers = lock(&RECEIVERS);
+        let Some(receiver) = receivers.get(&self.key) else {
+            return;
+        };
+        let empty = {
+            let mut members = lock(&receiver.members);
+            members.remove(self.id);
+            members.members.is_empty()
+        };
+        if empty && let Some(receiver) = receivers.remove(&self.key) {
+            receiver.task.abort();
+        }
+    }
+}
+
+/// Registers `router` with the `kind` receiver on `ifname`, binding its
+/// socket if this is the first instance there.
+pub(crate) fn subscribe(
+    kind: ReceiverKind,
+    ifname: &str,
+    filter: Filter,
+    router: RouterHandle,
+) -> io::Result<Subscription> {
+    let key = (ifname.to_string(), kind);
+    let id = NEXT_MEMBER_ID.fetch_add(1, Ordering::Relaxed);
+    let member = Member { id, filter, router };
+
+    let mut receivers = lock(&RECEIVERS);
+    match receivers.get(&key) {
+        Some(receiver) => lock(&receiver.members).insert(member),
+        None => {
+            let mut set = MemberSet::default();
+            set.insert(member);
+            let members = Arc::new(Mutex::new(set));
+            let task = spawn_receiver(kind, ifname, members.clone())?;
+            log::info!("Listening for {} traffic on {ifname}", kind.label());
+            receivers.insert(key.clone(), Receiver { members, task });
+        }
+    }
+    Ok(Subscription { key, id })
+}
+
+/// Nothing behind these locks can be left half-updated by a panic, so a
+/// poisoned one is still safe to use.
+fn lock<T>(mutex: &Mutex<T>) -> MutexGuard<'_, T> {
+    mutex.lock().unwrap_or_else(PoisonError::into_inner)
+}
+
+fn spawn_receiver(
+    kind: ReceiverKind,
+    ifname: &str,
+    members: Members,
+) -> io::Result<JoinHandle<()>> {
+    let name = ifname.to_string();
+    Ok(match kind {
+        ReceiverKind::Vrrp4 => {
+            let unspec_addr = IpAddr::V4(Ipv4Addr::UNSPECIFIED);
+            let listener = VrrpListener::bind(ifname, unspec_addr)?;
+            tokio::spawn(vrrp4_receiver(listener, name, members))
+        }
+        ReceiverKind::Vrrp6 => {
+            let unspec_addr = IpAddr::V6(Ipv6Addr::UNSPECIFIED);
+            let listener = VrrpListener::bind(ifname, unspec_addr)?;
+            tokio::spawn(vrrp6_receiver(listener, name, members))
+        }
+        ReceiverKind::Arp => {
+            let listener = ArpListener::bind(ifname)?;
+            tokio::spawn(arp_receiver(listener, members))
+        }
+        ReceiverKind::Ndp => {
+            let listener = NdpListener::bind(ifname)?;
+            tokio::spawn(ndp_receiver(listener, members))
+        }
+    })
+}
+
+async fn vrrp4_receiver(
+    listener: VrrpListener,
+    ifname: String,
+    members: Members,
+) {
+    let unspec_addr = IpAddr::V4(Ipv4Addr::UNSPECIFIED);
+    loop {
+        let (buf, _) = match listener.recv(unspec_addr).await {
+            Ok(pair) => pair,
+            Err(err) => {
+                log::warn!("Error receiving VRRP packet on {ifname}: {err}");
+                continue;
+            }
+        };
+
+        let Some(ip_packet) = Ipv4Packet::new(&buf) else {
+            log::warn!("Unable to read incoming IP packet on {ifname}");
+            continue;
+        };
+
+        dispatch_vrrp(
+            &ifname,
+            &members,
+            ip_packet.payload(),
+            IpAddr::V4(ip_packet.get_source()),
+            IpAddr::V4(ip_packet.get_destination()),
+            ip_packet.get_ttl(),
+        );
+    }
+}
+
+/// An IPv6 raw socket hands over just the VRRP message; the destination
+/// and hop limit come alongside it (see `VrrpListener::recv_v6`).
+async fn vrrp6_receiver(
+    listener: VrrpListener,
+    ifname: String,
+    members: Members,
+) {
+    loop {
+        let (buf, src, dst, hop_limit) = match listener.recv_v6().await {
+            Ok(received) => received,
+            Err(err) => {
+                log::warn!("Error receiving VRRPv6 packet on {ifname}: {err}");
+                continue;
+            }
+        };
+
+        dispatch_vrrp(
+            &ifname,
+            &members,
+            &buf,
+            IpAddr::V6(src),
+            IpAddr::V6(dst),
+            hop_limit,
+        );
+    }
+}
+
+fn dispatch_vrrp(
+    ifname: &str,
+    members: &Members,
+    payload: &[u8],
+    src: IpAddr,
+    dst: IpAddr,
+    ttl: u8,
+) {
+    // One of our own adverts, looped back.
+    if links::is_local_address(src) {
+        return;
+    }
+
+    let vrrp_packet = match VrrpPacket::decode(payload, src, dst) {
+        Ok(pkt) => pkt,
+        Err(err) => {
+            log_drop(ifname, err);
+            return;
+        }
+    };
+
+    let matched: Vec<(bool, RouterHandle)> = lock(members)
+        .members
+        .iter()
+        .filter(|member| member.filter.vrid == vrrp_packet.vrid)
+        .map(|member| {
+            (member.filter.accepts_source(src), member.router.clone())
+        })
+        .collect();
+    if matched.is_empty() {
+        log_drop(ifname, PacketError::UnknownVrid(vrrp_packet.vrid));
+        return;
+    }
+
+    for (from_peer, router) in matched {
+        if !from_peer {
+            log::trace!("dropping VRRP packet from non-peer {src}");
+            continue;
+        }
+        router.offer(Input::Vrrp {
+            packet: vrrp_packet.clone(),
+            src,
+            ttl,
+        });
+    }
+}
+
+async fn arp_receiver(listener: ArpListener, members: Members) {
+    loop {
+        let buf = match listener.recv().await {
+            Ok(buf) => buf,
+            Err(err) => {
+                log::warn!("Error receiving ARP packet: {err}");
+                continue;
+            }
+        };
+
+        let Some(eth_packet) = EthernetPacket::new(&buf) else {
+            continue;
+        };
+        if eth_packet.get_ethertype() != EtherTypes::Arp {
+            continue;
+        }
+        let Some(arp_packet) = ArpPacket::decode(eth_packet.payload()) else {
+            continue;
+        };
+        let target = Ipv4Addr::from(arp_packet.target_proto_address);
+        let Some(router) = lock(&members).for_vip(IpAddr::V4(target)) else {
+            continue;
+        };
+        router.offer(Input::Arp {
+            frame_dst: eth_packet.get_destination().octets(),
+            frame_src: eth_packet.get_source().octets(),
+            packet: arp_packet,
+        });
+    }
+}
+
+async fn ndp_receiver(listener: NdpListener, members: Members) {
+    loop {
+        let (payload, _) = match listener.recv().await {
+            Ok(pair) => pair,
+            Err(err) => {
+                log::warn!("Error receiving NDP packet: {err}");
+                continue;
+            }
+        };
+
+        let Some(ns) = NdpNeighborSolicitation::decode(&payload) else {
+            continue;
+        };
+        let target = IpAddr::V6(ns.target_address);
+        if let Some(router) = lock(&members).for_vip(target) {
+            router.offer(Input::Ndp(ns));
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use tokio::sync::mpsc;
+
+    use super::*;
+
+    #[test]
+    fn multicast_instances_accept_any_source() {
+        let filter = Filter {
+            vrid: 51,
+            peers: vec![],
+            vips: vec![],
+        };
+        assert!(filter.accepts_source("192.168.100.2".parse().unwrap()));
+    }
+
+    #[test]
+    fn unicast_instances_only_accept_their_peers() {
+        let filter = Filter {
+            vrid: 51,
+            peers: vec!["192.168.100.2".parse().unwrap()],
+            vips: vec![],
+        };
+        assert!(filter.accepts_source("192.168.100.2".parse().unwrap()));
+        assert!(!filter.accepts_source("192.168.100.3".parse().unwrap()));
+    }
+
+    fn member(id: u64, vips: &[&str]) -> (Member, mpsc::Receiver<Input>) {
+        let (router, inbox) = RouterHandle::detached(&format!("VR_{id}"));
+        let filter = Filter {
+            vrid: 51,
+            peers: vec![],
+            vips: vips.iter().map(|vip| vip.parse().unwrap()).collect(),
+        };
+        (Member { id, filter, router }, inbox)
+    }
+
+    /// Offers a Neighbor Solicitation to whoever answers for `vip`.
+    fn solicit(set: &MemberSet, vip: &str) -> bool {
+        let Ok(IpAddr::V6(target_address)) = vip.parse() else {
+            return false;
+        };
+        let Some(router) = set.for_vip(IpAddr::V6(target_address)) else {
+            return false;
+        };
+        router.offer(Input::Ndp(NdpNeighborSolicitation { target_address }));
+        true
+    }
+
+    #[test]
+    fn neighbor_traffic_only_reaches_the_owner_of_the_address() {
+        let mut set = MemberSet::default();
+        let (first, mut first_inbox) = member(1, &["fd00::1", "10.0.0.1"]);
+        let (second, mut second_inbox) = member(2, &["fd00::2"]);
+        set.insert(first);
+        set.insert(second);
+
+        assert!(solicit(&set, "fd00::2"));
+        assert!(second_inbox.try_recv().is_ok());
+        assert!(first_inbox.try_recv().is_err());
+
+        assert!(!solicit(&set, "fd00::3"));
+        assert!(first_inbox.try_recv().is_err());
+        assert!(second_inbox.try_recv().is_err());
+    }
+
+    #[test]
+    fn leaving_member_stops_receiving_and_shared_addresses_fall_back() {
+        let mut set = MemberSet::default();
+        let (first, mut first_inbox) = member(1, &["fd00::1"]);
+        let (second, _second_inbox) = member(2, &["fd00::1", "fd00::2"]);
+        set.insert(first);
+        set.insert(second);
+
+        set.remove(2);
+
+        assert!(!solicit(&set, "fd00::2"));
+        assert!(solicit(&set, "fd00::1"));
+        assert!(first_inbox.try_recv().is_ok());
+    }
+}
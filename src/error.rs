@@ -74,6 +74,50 @@ pub enum ConfigError {
     )]
     AdvertIntervalTooLarge { name: String, interval: u8 },
 
+    #[error(
+        "({name}) advertisement interval {interval_cs}cs is invalid for VRRPv{version}; v3 accepts 1-4095cs, v2 only whole seconds (multiples of 100cs)"
+    )]
+    InvalidAdvertIntervalCs {
+        name: String,
+        interval_cs: u16,
+        version: u8,
+    },
+
+    #[error(
+        "({name}) track script {script:?} needs a non-zero interval, timeout, rise and fall"
+    )]
+    InvalidTrackScript { name: String, script: String },
+
+    #[error(
+        "({name}) auth_password is only supported for VRRPv2 (RFC 5798 removed authentication); remove it or set \"version\": 2"
+    )]
+    AuthPasswordNeedsV2 { name: String },
+
+    #[error(
+        "({name}) auth_password must be 1-8 bytes long; VRRPv2 simple-text authentication only has room for 8"
+    )]
+    InvalidAuthPassword { name: String },
+
+    #[error(
+        "({name}) unicast_src_ip is set without any unicast_peers; add the peers or remove it"
+    )]
+    UnicastSrcWithoutPeers { name: String },
+
+    #[error(
+        "({name}) garp_repeat must be at least 1; a new MASTER always announces its virtual IP(s)"
+    )]
+    InvalidGarpRepeat { name: String },
+
+    #[error(
+        "sync group {group:?} names instance {instance:?}, but no instance has that name"
+    )]
+    UnknownSyncGroupMember { group: String, instance: String },
+
+    #[error(
+        "sync group {group:?} names instance {instance:?}, which is either shared by a v2 and a v3 instance or already in another sync group"
+    )]
+    SyncGroupMemberConflict { group: String, instance: String },
+
     #[error(
         "({name}) VRRPv2 only supports IPv4, but IPv6 address {address:?} was configured; remove it or set \"version\": 3"
     )]
@@ -88,9 +132,22 @@ pub enum NetworkError {
     #[error("interface {0} has no IPv4 address configured")]
     NoIpv4Address(String),
 
+    #[error(
+        "({name}) priority 255 is reserved for the address owner, but not every virtual address is assigned to {interface}; lower the priority or assign the addresses"
+    )]
+    NotAddressOwner { name: String, interface: String },
+
+    #[error(
+        "({name}) owns its virtual address(es) and starts as BACKUP (initial_state \"backup\"), but with preempt_mode on and no preempt_delay it would take them back on the first advert it hears; set a preempt_delay or turn preempt_mode off"
+    )]
+    OwnerPreemptsAtOnce { name: String },
+
     #[error("unable to open netlink connection: {0}")]
     NetlinkConnect(#[source] std::io::Error),
 
+    #[error("unable to dump links and addresses: {0}")]
+    LinkDump(#[source] rtnetlink::Error),
+
     #[error("problem fetching interface {name}: {source}")]
     InterfaceLookup {
         name: String,
@@ -122,6 +179,21 @@ pub enum NetworkError {
         source: rtnetlink::Error,
     },
 
+    #[error(
+        "interface {0} already exists and is not an ipvlan; refusing to touch it"
+    )]
+    NotIpVlan(String),
+
+    #[error("ipvlan {name} already exists but {reason}; refusing to reuse it")]
+    IpVlanMismatch { name: String, reason: String },
+
+    #[error("unable to create ipvlan interface {name}: {source}")]
+    IpVlanCreate {
+        name: String,
+        #[source]
+        source: rtnetlink::Error,
+    },
+
     #[error("unable to bind {kind} listening socket on {iface}: {source}")]
     SocketBind {
         kind: &'static str,
@@ -132,9 +204,6 @@ pub enum NetworkError {
 
     #[error("unable to install SIGTERM handler: {0}")]
     SignalHandler(#[source] std::io::Error),
-
-    #[error("unable to lock virtual router state")]
-    LockPoisoned,
 }
 
 #[derive(Debug, Error)]
@@ -151,13 +220,13 @@ pub enum PacketError {
     #[error("invalid VRRP checksum")]
     BadChecksum,
 
-    #[error("VRID {received} does not match configured VRID {expected}")]
-    VridMismatch { expected: u8, received: u8 },
+    #[error("no instance is configured for VRID {0} on this interface")]
+    UnknownVrid(u8),
 
     #[error(
-        "advertisement interval {received} does not match configured interval {expected}"
+        "advertisement interval {received}cs does not match configured interval {expected}cs"
     )]
-    AdvertIntervalMismatch { expected: u8, received: u8 },
+    AdvertIntervalMismatch { expected: u16, received: u16 },
 
     #[error(
         "IP address count {received} does not match configured count {expected}"
@@ -166,6 +235,14 @@ pub enum PacketError {
 
     #[error("IP address list does not match configured addresses")]
     IpListMismatch,
+
+    #[error("local router is the address owner; ignoring advertisement")]
+    LocalAddressOwner,
+
+    #[error(
+        "authentication {received} does not match configured authentication {expected} (or the password differs)"
+    )]
+    AuthMismatch { expected: String, received: String },
 }
 
 #[derive(Debug, Error)]
@@ -1,22 +1,37 @@
+use std::collections::{HashMap, HashSet};
 use std::net::{IpAddr, Ipv4Addr, Ipv6Addr};
+use std::process::{Output, Stdio};
 use std::str::FromStr;
+use std::sync::{LazyLock, Mutex, PoisonError};
 
 use futures_util::stream::TryStreamExt;
 use ipnet::{IpNet, Ipv4Net, Ipv6Net};
+use netlink_packet_route::AddressFamily as NetlinkAddressFamily;
 use netlink_packet_route::address::AddressAttribute;
+use netlink_packet_route::route::{RouteAddress, RouteAttribute, RouteMessage};
 use pnet::datalink::{self, NetworkInterface};
 use rand::Rng;
 use rand::distributions::Alphanumeric;
 use rtnetlink::packet_route::link::{
-    InfoKind, LinkAttribute, LinkInfo, LinkMessage, MacVlanMode,
+    InfoData, InfoIpVlan, InfoKind, IpVlanMode, LinkAttribute, LinkFlags,
+    LinkInfo, LinkMessage, MacVlanMode,
 };
-use rtnetlink::{AddressMessageBuilder, Handle, LinkMacVlan, new_connection};
+use rtnetlink::{
+    AddressMessageBuilder, Handle, LinkMacVlan, LinkMessageBuilder, LinkUnspec,
+    RouteMessageBuilder, new_connection,
+};
+use tokio::io::AsyncWriteExt;
+use tokio::process::Command;
+use tokio::task::JoinHandle;
 
-use crate::config::Config;
+use crate::announce::GarpSettings;
+use crate::config::{Config, LinkBackend};
 use crate::error::NetworkError;
-use crate::packet::VrrpPacket;
+use crate::network;
+use crate::packet::{V2Auth, VrrpPacket};
 use crate::router::{VirtualRouter, VirtualRouterParams};
-use crate::{AddressAction, NetResult};
+use crate::sync::SyncGroupHandle;
+use crate::{AddressAction, NetResult, VrrpVersion};
 
 pub(crate) fn get_interface(name: &str) -> NetResult<NetworkInterface> {
     let interface_names_match = |iface: &NetworkInterface| iface.name == name;
@@ -54,6 +69,29 @@ pub(crate) fn primary_ipv6(interface: &NetworkInterface) -> Option<Ipv6Addr> {
     })
 }
 
+/// Whether every one of `vips` is already a real address on `interface`,
+/// making this host their address owner. `false` for an empty list.
+pub(crate) fn owns_addresses(
+    interface: &NetworkInterface,
+    vips: &[IpAddr],
+) -> bool {
+    !vips.is_empty()
+        && vips
+            .iter()
+            .all(|vip| interface.ips.iter().any(|ip| ip.ip() == *vip))
+}
+
+/// Those of `vips` that are already real addresses on `interface`.
+pub(crate) fn addresses_on(
+    interface: &NetworkInterface,
+    vips: &[IpAddr],
+) -> Vec<IpAddr> {
+    vips.iter()
+        .copied()
+        .filter(|vip| interface.ips.iter().any(|ip| ip.ip() == *vip))
+        .collect()
+}
+
 /// Takes the configs that have been received and converts them into a virtual
 ///  router instance.
 pub fn config_to_vr(conf: Config) -> VirtualRouter {
@@ -94,48 +132,98 @@ pub fn config_to_vr(conf: Config) -> VirtualRouter {
         ipv4_addresses,
         ipv6_addresses,
         priority: conf.priority,
-        advert_interval: conf.advert_interval,
+        advert_interval_cs: conf
+            .advert_interval_cs
+            .unwrap_or(conf.advert_interval as u16 * 100),
         preempt_mode: conf.preempt_mode,
+        preempt_delay: conf.preempt_delay,
+        accept_mode: conf.accept_mode,
+        startup_delay: conf.startup_delay,
+        initial_state: conf.initial_state,
+        track_interfaces: conf.track_interfaces,
+        track_scripts: conf.track_scripts,
+        track_routes: conf.track_routes,
+        // Only `configs_to_vrs` sees every member of a group.
+        sync_group: None,
+        unicast_peers: conf.unicast_peers,
+        unicast_src_ip: conf.unicast_src_ip,
+        auth: conf
+            .auth_password
+            .as_deref()
+            .map_or(V2Auth::None, V2Auth::simple_text),
+        garp: GarpSettings {
+            repeat: conf.garp_repeat,
+            interval_ms: conf.garp_interval_ms,
+            refresh: conf.garp_refresh,
+            reply: conf.garp_reply,
+        },
         network_interface: conf.interface_name,
+        use_virtual_mac: conf.use_virtual_mac,
+        link_backend: conf.link_backend,
     });
-    log::info!("({}) Entered {:?} state.", vr.name, vr.fsm.state);
+    log::info!(
+        "({}) Entered {:?} state (accept_mode: {}, auth: {}).",
+        vr.name,
+        vr.fsm.state,
+        vr.accept_mode,
+        vr.auth
+    );
     vr
 }
 
+/// `config_to_vr` for every configured instance, connecting the members of
+/// each sync group to one another.
+pub fn configs_to_vrs(configs: Vec<Config>) -> Vec<VirtualRouter> {
+    let mut groups: HashMap<String, SyncGroupHandle> = HashMap::new();
+    configs
+        .into_iter()
+        .map(|conf| {
+            let group = conf.sync_group.as_ref().map(|name| {
+                groups
+                    .entry(name.clone())
+                    .or_insert_with(|| SyncGroupHandle::new(name))
+                    .clone()
+            });
+            let mut vrouter = config_to_vr(conf);
+            vrouter.sync_group = group;
+            vrouter
+        })
+        .collect()
+}
+
+/// The rtnetlink handle shared by every instance for address and link
+/// changes, opened on first use rather than per call, and reopened if its
+/// connection ever goes away.
+static NETLINK: Mutex<Option<(Handle, JoinHandle<()>)>> = Mutex::new(None);
+
+fn netlink_handle() -> NetResult<Handle> {
+    let mut cached = NETLINK.lock().unwrap_or_else(PoisonError::into_inner);
+    if let Some((handle, connection)) = cached.as_ref()
+        && !connection.is_finished()
+    {
+        return Ok(handle.clone());
+    }
+    let (connection, handle, _) =
+        new_connection().map_err(NetworkError::NetlinkConnect)?;
+    *cached = Some((handle.clone(), tokio::spawn(connection)));
+    Ok(handle)
+}
+
 /// Adds/removes the given virtual IP addresses (IPv4 or IPv6, inferred per
 /// address) on `interface_name` via Netlink (equivalent to
 /// `ip address add/delete <addr> dev <iface>`).
-///
-/// Bridges into async rtnetlink code from what is otherwise a synchronous
-/// call chain, so this must be invoked from within a multi-threaded tokio
-/// runtime.
-pub(crate) fn virtual_address_action(
+pub(crate) async fn virtual_address_action(
     action: AddressAction,
     addresses: &[String],
     interface_name: &str,
 ) {
-    tokio::task::block_in_place(|| {
-        tokio::runtime::Handle::current().block_on(apply_address_action(
-            action,
-            addresses,
-            interface_name,
-        ));
-    });
-}
-
-async fn apply_address_action(
-    action: AddressAction,
-    addresses: &[String],
-    interface_name: &str,
-) {
-    let (connection, handle, _) = match new_connection() {
-        Ok(conn) => conn,
+    let handle = match netlink_handle() {
+        Ok(handle) => handle,
         Err(err) => {
-            log::error!("Unable to open netlink connection: {err}");
+            log::error!("{err}");
             return;
         }
     };
-    tokio::spawn(connection);
 
     let mut links = handle
         .link()
@@ -198,6 +286,286 @@ async fn apply_address_action(
     }
 }
 
+/// nftables table holding this instance's Accept_Mode filter. Keyed by
+/// version and vrid, which `validate_configs` guarantees are unique.
+fn accept_mode_table(version: VrrpVersion, vrid: u8) -> String {
+    format!("failover_v{}_{vrid}", version.as_u8())
+}
+
+/// The `nft -f -` script that installs (`Add`) or removes (`Delete`) the
+/// filter dropping locally-destined traffic to `v4`/`v6`. Both start by
+/// declaring then deleting the table, so either one is idempotent.
+fn accept_mode_script(
+    action: &AddressAction,
+    table: &str,
+    v4: &[Ipv4Addr],
+    v6: &[Ipv6Addr],
+) -> String {
+    let mut script = format!("table inet {table}\ndelete table inet {table}\n");
+    if let AddressAction::Delete = action {
+        return script;
+    }
+
+    script.push_str(&format!("table inet {table} {{\n"));
+    script.push_str("    chain accept_mode {\n");
+    script.push_str(
+        "        type filter hook input priority filter; policy accept;\n",
+    );
+    // Neighbor discovery for the virtual IPv6 addresses must still work.
+    script.push_str(
+        "        icmpv6 type { nd-neighbor-solicit, nd-neighbor-advert } accept\n",
+    );
+    let join = |addrs: Vec<String>| addrs.join(", ");
+    if !v4.is_empty() {
+        let addrs = join(v4.iter().map(|a| a.to_string()).collect());
+        script.push_str(&format!("        ip daddr {{ {addrs} }} drop\n"));
+    }
+    if !v6.is_empty() {
+        let addrs = join(v6.iter().map(|a| a.to_string()).collect());
+        script.push_str(&format!("        ip6 daddr {{ {addrs} }} drop\n"));
+    }
+    script.push_str("    }\n}\n");
+    script
+}
+
+/// Installs or removes the nftables rule implementing `accept_mode: false`
+/// for `vrouter`'s virtual addresses, bar its `host_addresses`
+/// (equivalent to `nft -f <script>`).
+/// A no-op when the router isn't filtering VIP traffic at all.
+pub(crate) async fn accept_mode_action(
+    action: AddressAction,
+    vrouter: &VirtualRouter,
+) {
+    if !vrouter.filters_vip_traffic() {
+        return;
+    }
+
+    let table = accept_mode_table(vrouter.version, vrouter.vrid);
+    let v4: Vec<Ipv4Addr> = vrouter
+        .ipv4_addrs()
+        .into_iter()
+        .filter(|a| !vrouter.is_host_address(IpAddr::V4(*a)))
+        .collect();
+    let v6: Vec<Ipv6Addr> = vrouter
+        .ipv6_addrs()
+        .into_iter()
+        .filter(|a| !vrouter.is_host_address(IpAddr::V6(*a)))
+        .collect();
+    let script = accept_mode_script(&action, &table, &v4, &v6);
+
+    match run_nft(&script).await {
+        Ok(output) if output.status.success() => {
+            log::info!(
+                "({}) accept_mode off: {action} filter for virtual address(es) in nftables table {table}",
+                vrouter.name
+            );
+        }
+        Ok(output) => {
+            log::warn!(
+                "({}) nft exited with {} while trying to {action} table {table}: {}",
+                vrouter.name,
+                output.status,
+                String::from_utf8_lossy(&output.stderr).trim()
+            );
+        }
+        Err(err) => {
+            log::warn!(
+                "({}) unable to run nft to {action} table {table}: {err}",
+                vrouter.name
+            );
+        }
+    }
+}
+
+/// Runs `script` through `nft -f -`.
+async fn run_nft(script: &str) -> std::io::Result<Output> {
+    let mut child = Command::new("nft")
+        .args(["-f", "-"])
+        .stdin(Stdio::piped())
+        .stderr(Stdio::piped())
+        .spawn()?;
+    if let Some(mut stdin) = child.stdin.take() {
+        stdin.write_all(script.as_bytes()).await?;
+    }
+    child.wait_with_output().await
+}
+
+/// nftables table discarding frames sent to the virtual MAC on the
+/// mac-vlan `link`, i.e. one per parent interface, vrid and family.
+fn virtual_mac_discard_table(link: &str) -> String {
+    format!("failover_discard_{}", link.replace('-', "_"))
+}
+
+/// The `nft -f -` script that installs (`discard`) or removes the ingress
+/// filter dropping frames sent to `mac` on `link`. Idempotent, like
+/// `accept_mode_script`.
+fn virtual_mac_discard_script(
+    discard: bool,
+    table: &str,
+    link: &str,
+    mac: [u8; 6],
+) -> String {
+    let mut script =
+        format!("table netdev {table}\ndelete table netdev {table}\n");
+    if !discard {
+        return script;
+    }
+
+    let mac: Vec<String> = mac.iter().map(|b| format!("{b:02x}")).collect();
+    script.push_str(&format!("table netdev {table} {{\n"));
+    script.push_str("    chain backup_discard {\n");
+    script.push_str(&format!(
+        "        type filter hook ingress device \"{link}\" priority filter; policy accept;\n"
+    ));
+    script.push_str(&format!("        ether daddr {} drop\n", mac.join(":")));
+    script.push_str("    }\n}\n");
+    script
+}
+
+/// The instances running on one mac-vlan, by version: a v2 and a v3
+/// instance with the same vrid share the v4 one, so its frames may only be
+/// discarded while neither is MASTER.
+#[derive(Debug, Default)]
+struct VirtualMacUsers {
+    users: HashSet<u8>,
+    masters: HashSet<u8>,
+    /// Whether the discard filter is in place; unknown until first set, as
+    /// an earlier run may have left one behind.
+    discarding: Option<bool>,
+}
+
+impl VirtualMacUsers {
+    /// Records whether `version` is MASTER, returning whether the filter
+    /// now has to go in (`Some(true)`) or come out (`Some(false)`).
+    fn update(&mut self, version: u8, master: bool) -> Option<bool> {
+        self.users.insert(version);
+        if master {
+            self.masters.insert(version);
+        } else {
+            self.masters.remove(&version);
+        }
+        self.change_to(self.masters.is_empty())
+    }
+
+    /// `update` for `version` shutting down; the last one out removes the
+    /// filter.
+    fn leave(&mut self, version: u8) -> Option<bool> {
+        self.users.remove(&version);
+        self.masters.remove(&version);
+        self.change_to(!self.users.is_empty() && self.masters.is_empty())
+    }
+
+    fn change_to(&self, discard: bool) -> Option<bool> {
+        (self.discarding != Some(discard)).then_some(discard)
+    }
+}
+
+/// Held across the `nft` run, so the filter on a shared mac-vlan always
+/// ends up matching the latest update to its users.
+static VIRTUAL_MAC_USERS: LazyLock<
+    tokio::sync::Mutex<HashMap<String, VirtualMacUsers>>,
+> = LazyLock::new(|| tokio::sync::Mutex::new(HashMap::new()));
+
+/// `vrouter`'s mac-vlans and the virtual MAC each carries. None with the
+/// ipvlan backend or without a virtual MAC, which have no MAC of their own
+/// to discard frames for.
+fn virtual_mac_links(vrouter: &VirtualRouter) -> Vec<(String, [u8; 6])> {
+    if !vrouter.use_virtual_mac || vrouter.link_backend != LinkBackend::Macvlan
+    {
+        return vec![];
+    }
+    let mut links = vec![(
+        vrouter.mac_vlan_interface_v4.clone(),
+        AddressFamily::V4.virtual_mac(vrouter.vrid),
+    )];
+    if let Some(v6_iface) = &vrouter.mac_vlan_interface_v6 {
+        links.push((
+            v6_iface.clone(),
+            AddressFamily::V6.virtual_mac(vrouter.vrid),
+        ));
+    }
+    links
+}
+
+/// Discards (`discard`) or accepts again frames sent to `vrouter`'s
+/// virtual MAC(s), with an nftables ingress filter on each of its
+/// mac-vlans: a BACKUP MUST discard them (RFC 3768/5798 section 6), or a
+/// host with forwarding enabled may still route them. A mac-vlan shared
+/// with a MASTER sibling keeps accepting them.
+pub(crate) async fn virtual_mac_discard_action(
+    discard: bool,
+    vrouter: &VirtualRouter,
+) {
+    let version = vrouter.version.as_u8();
+    for (link, mac) in virtual_mac_links(vrouter) {
+        let mut links = VIRTUAL_MAC_USERS.lock().await;
+        let users = links.entry(link.clone()).or_default();
+        if let Some(discard) = users.update(version, !discard)
+            && apply_virtual_mac_discard(discard, &link, mac, vrouter).await
+        {
+            users.discarding = Some(discard);
+        }
+    }
+}
+
+/// Unregisters `vrouter` from its mac-vlans on shutdown, removing the
+/// discard filter from any it was the last instance on.
+pub(crate) async fn forget_virtual_mac(vrouter: &VirtualRouter) {
+    let version = vrouter.version.as_u8();
+    for (link, mac) in virtual_mac_links(vrouter) {
+        let mut links = VIRTUAL_MAC_USERS.lock().await;
+        let Some(users) = links.get_mut(&link) else {
+            continue;
+        };
+        if let Some(discard) = users.leave(version)
+            && apply_virtual_mac_discard(discard, &link, mac, vrouter).await
+        {
+            users.discarding = Some(discard);
+        }
+        if users.users.is_empty() {
+            links.remove(&link);
+        }
+    }
+}
+
+/// Installs or removes the discard filter on `link`, returning whether
+/// `nft` succeeded.
+async fn apply_virtual_mac_discard(
+    discard: bool,
+    link: &str,
+    mac: [u8; 6],
+    vrouter: &VirtualRouter,
+) -> bool {
+    let table = virtual_mac_discard_table(link);
+    let script = virtual_mac_discard_script(discard, &table, link, mac);
+    let what = if discard { "discarding" } else { "accepting" };
+    match run_nft(&script).await {
+        Ok(output) if output.status.success() => {
+            log::info!(
+                "({}) {what} frames sent to the virtual MAC on {link}",
+                vrouter.name
+            );
+            true
+        }
+        Ok(output) => {
+            log::warn!(
+                "({}) nft exited with {} while {what} frames sent to the virtual MAC on {link}: {}",
+                vrouter.name,
+                output.status,
+                String::from_utf8_lossy(&output.stderr).trim()
+            );
+            false
+        }
+        Err(err) => {
+            log::warn!(
+                "({}) unable to run nft for table {table}: {err}",
+                vrouter.name
+            );
+            false
+        }
+    }
+}
+
 fn fnv1a_hash(input: &str) -> u32 {
     const FNV_OFFSET_BASIS: u32 = 0x811c_9dc5;
     const FNV_PRIME: u32 = 0x0100_0193;
@@ -234,12 +602,12 @@ impl AddressFamily {
     }
 }
 
-/// mvlan interface name:
+/// mac-vlan/ipvlan interface name:
 ///     `fover4-{vrid}-{4 hex digit hash of the parent name}` (or `fover6-`
 ///     for the IPv6 side). Kept to 4 hex digits (rather than 5) so that,
 ///     with a 3-digit vrid, the name stays within Linux's 15-character
 ///     `IFNAMSIZ` limit once the family digit is included.
-fn mac_vlan_name(
+fn vrrp_link_name(
     parent_ifname: &str,
     vrid: u8,
     family: AddressFamily,
@@ -272,7 +640,36 @@ fn link_is_mac_vlan(link: &LinkMessage) -> bool {
     false
 }
 
-fn link_mac_address(link: &LinkMessage) -> Option<Vec<u8>> {
+fn link_is_ip_vlan(link: &LinkMessage) -> bool {
+    for attr in &link.attributes {
+        if let LinkAttribute::LinkInfo(infos) = attr {
+            for info in infos {
+                if let LinkInfo::Kind(InfoKind::IpVlan) = info {
+                    return true;
+                }
+            }
+        }
+    }
+    false
+}
+
+fn link_ip_vlan_mode(link: &LinkMessage) -> Option<IpVlanMode> {
+    for attr in &link.attributes {
+        if let LinkAttribute::LinkInfo(infos) = attr {
+            for info in infos {
+                if let LinkInfo::Data(InfoData::IpVlan(data)) = info {
+                    return data.iter().find_map(|nla| match nla {
+                        InfoIpVlan::Mode(mode) => Some(*mode),
+                        _ => None,
+                    });
+                }
+            }
+        }
+    }
+    None
+}
+
+pub(crate) fn link_mac_address(link: &LinkMessage) -> Option<Vec<u8>> {
     link.attributes.iter().find_map(|attr| match attr {
         LinkAttribute::Address(mac) => Some(mac.clone()),
         _ => None,
@@ -286,6 +683,138 @@ fn link_parent_index(link: &LinkMessage) -> Option<u32> {
     })
 }
 
+pub(crate) fn link_name(link: &LinkMessage) -> Option<&str> {
+    link.attributes.iter().find_map(|attr| match attr {
+        LinkAttribute::IfName(name) => Some(name.as_str()),
+        _ => None,
+    })
+}
+
+/// Name of the link in a `RTM_NEWLINK` message, and whether it's
+/// administratively up with carrier (`IFF_UP` and `IFF_LOWER_UP`) -- the
+/// condition interface tracking treats as healthy.
+pub(crate) fn link_carrier(link: &LinkMessage) -> Option<(&str, bool)> {
+    let up = link
+        .header
+        .flags
+        .contains(LinkFlags::Up | LinkFlags::LowerUp);
+    Some((link_name(link)?, up))
+}
+
+/// Destination prefix and routing table of a route message. A route with no
+/// `RTA_DST` is a default route (`0.0.0.0/0` or `::/0`).
+pub(crate) fn route_prefix(route: &RouteMessage) -> Option<(IpNet, u32)> {
+    let prefix_len = route.header.destination_prefix_length;
+    let mut table = route.header.table as u32;
+    let mut dst = None;
+    for attr in &route.attributes {
+        match attr {
+            RouteAttribute::Table(id) => table = *id,
+            RouteAttribute::Destination(RouteAddress::Inet(addr)) => {
+                dst = Some(IpAddr::V4(*addr));
+            }
+            RouteAttribute::Destination(RouteAddress::Inet6(addr)) => {
+                dst = Some(IpAddr::V6(*addr));
+            }
+            _ => {}
+        }
+    }
+
+    let dst = match (dst, route.header.address_family) {
+        (Some(dst), _) => dst,
+        (None, NetlinkAddressFamily::Inet) => IpAddr::V4(Ipv4Addr::UNSPECIFIED),
+        (None, NetlinkAddressFamily::Inet6) => {
+            IpAddr::V6(Ipv6Addr::UNSPECIFIED)
+        }
+        (None, _) => return None,
+    };
+    Some((IpNet::new(dst, prefix_len).ok()?, table))
+}
+
+/// Every (prefix, table) currently in the kernel's IPv4 and IPv6 routing
+/// tables (equivalent to `ip route show table all` for both families).
+pub(crate) async fn installed_routes(
+    handle: &Handle,
+) -> Result<Vec<(IpNet, u32)>, rtnetlink::Error> {
+    let mut routes = vec![];
+    for request in [
+        RouteMessageBuilder::<Ipv4Addr>::new().build(),
+        RouteMessageBuilder::<Ipv6Addr>::new().build(),
+    ] {
+        let mut dump = handle.route().get(request).execute();
+        while let Some(route) = dump.try_next().await? {
+            routes.extend(route_prefix(&route));
+        }
+    }
+    Ok(routes)
+}
+
+/// Creates (or reuses) this instance's link for `family` on its
+/// vrid/parent interface, using the configured `backend`.
+pub(crate) async fn create_link(
+    backend: LinkBackend,
+    parent_ifname: &str,
+    vrid: u8,
+    family: AddressFamily,
+) -> NetResult<String> {
+    match backend {
+        LinkBackend::Macvlan => {
+            create_mac_vlan(parent_ifname, vrid, family).await
+        }
+        LinkBackend::Ipvlan => {
+            create_ip_vlan(parent_ifname, vrid, family).await
+        }
+    }
+}
+
+async fn parent_link_index(
+    handle: &Handle,
+    parent_ifname: &str,
+) -> NetResult<u32> {
+    let mut parents = handle
+        .link()
+        .get()
+        .match_name(parent_ifname.to_string())
+        .execute();
+    match parents.try_next().await {
+        Ok(Some(link)) => Ok(link.header.index),
+        Ok(None) => {
+            Err(NetworkError::InterfaceNotFound(parent_ifname.to_string()))
+        }
+        Err(source) => Err(NetworkError::InterfaceLookup {
+            name: parent_ifname.to_string(),
+            source,
+        }),
+    }
+}
+
+async fn existing_link(
+    handle: &Handle,
+    name: &str,
+) -> NetResult<Option<LinkMessage>> {
+    let mut existing =
+        handle.link().get().match_name(name.to_string()).execute();
+    match existing.try_next().await {
+        Ok(link) => Ok(link),
+        Err(err) if is_no_such_device(&err) => Ok(None),
+        Err(source) => Err(NetworkError::InterfaceLookup {
+            name: name.to_string(),
+            source,
+        }),
+    }
+}
+
+/// Leaves ARP for the virtual IPv4 address(es) on `name` to
+/// `handle_incoming_arp_pkt`.
+async fn disable_kernel_arp(name: &str) {
+    let arp_ignore_path = format!("/proc/sys/net/ipv4/conf/{name}/arp_ignore");
+    if let Err(err) = tokio::fs::write(&arp_ignore_path, b"8").await {
+        log::warn!(
+            "Unable to disable kernel ARP replies on {name} ({arp_ignore_path}): {err}"
+        );
+    }
+}
+
 /// Creates (or reuses) the mac-vlan interface for `family` on this
 /// instance's vrid/parent interface.
 ///
@@ -294,77 +823,47 @@ fn link_parent_index(link: &LinkMessage) -> Option<u32> {
 /// expected to share the same `fover4-...` mac-vlan. Reuse requires it
 /// actually be a mac-vlan, carry this family's virtual MAC, and hang off
 /// the same parent; any mismatch is a hard error. Only ever deleted on
-/// teardown (`delete_mac_vlan`), once no addresses remain on it.
-pub(crate) async fn create_mac_vlan(
+/// teardown (`delete_link`), once no addresses remain on it.
+async fn create_mac_vlan(
     parent_ifname: &str,
     vrid: u8,
     family: AddressFamily,
 ) -> NetResult<String> {
-    let name = mac_vlan_name(parent_ifname, vrid, family);
+    let name = vrrp_link_name(parent_ifname, vrid, family);
     let virtual_mac = family.virtual_mac(vrid);
 
-    let (connection, handle, _) =
-        new_connection().map_err(NetworkError::NetlinkConnect)?;
-    tokio::spawn(connection);
+    let handle = netlink_handle()?;
 
-    let mut parents = handle
-        .link()
-        .get()
-        .match_name(parent_ifname.to_string())
-        .execute();
-    let parent_index = match parents.try_next().await {
-        Ok(Some(link)) => link.header.index,
-        Ok(None) => {
-            return Err(NetworkError::InterfaceNotFound(
-                parent_ifname.to_string(),
-            ));
-        }
-        Err(source) => {
-            return Err(NetworkError::InterfaceLookup {
-                name: parent_ifname.to_string(),
-                source,
-            });
-        }
-    };
+    let parent_index = parent_link_index(&handle, parent_ifname).await?;
 
     // Check if an interface with the name already exists.
-    let mut existing = handle.link().get().match_name(name.clone()).execute();
-    match existing.try_next().await {
-        Ok(Some(link)) => {
-            if !link_is_mac_vlan(&link) {
-                return Err(NetworkError::NotMacVlan(name));
-            }
-
-            if link_mac_address(&link).as_deref()
-                != Some(virtual_mac.as_slice())
-            {
-                return Err(NetworkError::MacVlanMismatch {
-                    name,
-                    reason:
-                        "its MAC address doesn't match this instance's virtual MAC"
-                            .to_string(),
-                });
-            }
-
-            if link_parent_index(&link) != Some(parent_index) {
-                return Err(NetworkError::MacVlanMismatch {
-                    name,
-                    reason: format!(
-                        "it belongs to a different parent interface than {parent_ifname}"
-                    ),
-                });
-            }
+    if let Some(link) = existing_link(&handle, &name).await? {
+        if !link_is_mac_vlan(&link) {
+            return Err(NetworkError::NotMacVlan(name));
+        }
 
-            log::info!(
-                "Reusing existing mac-vlan {name} (parent {parent_ifname}, vrid {vrid})"
-            );
-            return Ok(name);
+        if link_mac_address(&link).as_deref() != Some(virtual_mac.as_slice()) {
+            return Err(NetworkError::MacVlanMismatch {
+                name,
+                reason:
+                    "its MAC address doesn't match this instance's virtual MAC"
+                        .to_string(),
+            });
         }
-        Ok(None) => {}
-        Err(err) if is_no_such_device(&err) => {}
-        Err(source) => {
-            return Err(NetworkError::InterfaceLookup { name, source });
+
+        if link_parent_index(&link) != Some(parent_index) {
+            return Err(NetworkError::MacVlanMismatch {
+                name,
+                reason: format!(
+                    "it belongs to a different parent interface than {parent_ifname}"
+                ),
+            });
         }
+
+        log::info!(
+            "Reusing existing mac-vlan {name} (parent {parent_ifname}, vrid {vrid})"
+        );
+        return Ok(name);
     }
 
     let message = LinkMacVlan::new(&name, parent_index, MacVlanMode::Bridge)
@@ -377,49 +876,100 @@ pub(crate) async fn create_mac_vlan(
     }
 
     if family == AddressFamily::V4 {
-        let arp_ignore_path =
-            format!("/proc/sys/net/ipv4/conf/{name}/arp_ignore");
-        if let Err(err) = tokio::fs::write(&arp_ignore_path, b"8").await {
-            log::warn!(
-                "Unable to disable kernel ARP replies on {name} ({arp_ignore_path}): {err}"
-            );
-        }
+        disable_kernel_arp(&name).await;
     }
 
     log::info!("Created mac-vlan {name} (parent {parent_ifname}, vrid {vrid})");
     Ok(name)
 }
 
-/// Tears down the mac-vlan interface created by [`create_mac_vlan`], but only
-/// if there are no addresses on the interface.
-pub(crate) fn delete_mac_vlan(name: &str) {
-    tokio::task::block_in_place(|| {
-        tokio::runtime::Handle::current().block_on(apply_delete_mac_vlan(name));
-    });
+/// Creates (or reuses) an L2-mode ipvlan interface for `family` on this
+/// instance's vrid/parent interface. Same naming and reuse rules as
+/// [`create_mac_vlan`], except that an ipvlan always shares its parent's
+/// MAC, so reuse checks the mode instead.
+async fn create_ip_vlan(
+    parent_ifname: &str,
+    vrid: u8,
+    family: AddressFamily,
+) -> NetResult<String> {
+    let name = vrrp_link_name(parent_ifname, vrid, family);
+
+    let handle = netlink_handle()?;
+
+    let parent_index = parent_link_index(&handle, parent_ifname).await?;
+
+    if let Some(link) = existing_link(&handle, &name).await? {
+        if !link_is_ip_vlan(&link) {
+            return Err(NetworkError::NotIpVlan(name));
+        }
+
+        if link_ip_vlan_mode(&link) != Some(IpVlanMode::L2) {
+            return Err(NetworkError::IpVlanMismatch {
+                name,
+                reason: "it isn't in L2 mode".to_string(),
+            });
+        }
+
+        if link_parent_index(&link) != Some(parent_index) {
+            return Err(NetworkError::IpVlanMismatch {
+                name,
+                reason: format!(
+                    "it belongs to a different parent interface than {parent_ifname}"
+                ),
+            });
+        }
+
+        log::info!(
+            "Reusing existing ipvlan {name} (parent {parent_ifname}, vrid {vrid})"
+        );
+        return Ok(name);
+    }
+
+    let message =
+        LinkMessageBuilder::<LinkUnspec>::new_with_info_kind(InfoKind::IpVlan)
+            .name(name.clone())
+            .link(parent_index)
+            .set_info_data(InfoData::IpVlan(vec![InfoIpVlan::Mode(
+                IpVlanMode::L2,
+            )]))
+            .up()
+            .build();
+
+    if let Err(source) = handle.link().add(message).execute().await {
+        return Err(NetworkError::IpVlanCreate { name, source });
+    }
+
+    if family == AddressFamily::V4 {
+        disable_kernel_arp(&name).await;
+    }
+
+    log::info!("Created ipvlan {name} (parent {parent_ifname}, vrid {vrid})");
+    Ok(name)
 }
 
-async fn apply_delete_mac_vlan(name: &str) {
-    let (connection, handle, _) = match new_connection() {
-        Ok(conn) => conn,
+/// Tears down the interface created by [`create_link`], but only if there
+/// are no addresses on the interface, closing the send sockets kept on it.
+pub(crate) async fn delete_link(backend: LinkBackend, name: &str) {
+    let handle = match netlink_handle() {
+        Ok(handle) => handle,
         Err(err) => {
-            log::error!("Unable to open netlink connection: {err}");
+            log::error!("{err}");
             return;
         }
     };
-    tokio::spawn(connection);
 
     let mut links = handle.link().get().match_name(name.to_string()).execute();
     let index = match links.try_next().await {
         Ok(Some(link)) => link.header.index,
         Ok(None) => {
             log::warn!(
-                "mac-vlan interface {name} not found; nothing to delete"
+                "{backend} interface {name} not found; nothing to delete"
             );
             return;
         }
         Err(err) => {
             log::error!(
-                "Problem fetching mac-vlan interface {name} for deletion: {err}"
+                "Problem fetching {backend} interface {name} for deletion: {err}"
             );
             return;
         }
@@ -429,7 +979,7 @@ async fn apply_delete_mac_vlan(name: &str) {
         Ok(0) => {}
         Ok(remaining) => {
             log::info!(
-                "mac-vlan {name} still has {remaining} address(es) assigned (likely still in use by another instance); leaving it up"
+                "{backend} {name} still has {remaining} address(es) assigned (likely still in use by another instance); leaving it up"
             );
             return;
         }
@@ -442,8 +992,10 @@ async fn apply_delete_mac_vlan(name: &str) {
     }
 
     if let Err(err) = handle.link().del(index).execute().await {
-        log::error!("Problem deleting mac-vlan interface {name}: {err}");
+        log::error!("Problem deleting {backend} interface {name}: {err}");
+        return;
     }
+    network::close_send_sockets(name);
 }
 
 async fn remaining_address_count(
@@ -484,6 +1036,22 @@ pub(crate) fn random_vr_name() -> String {
     format!("VR_{val}")
 }
 
+/// Lists the instances a SIGUSR1 releases, one name per line. While it is
+/// missing or empty, SIGUSR1 releases every instance in the process.
+pub(crate) const RELEASE_FILE: &str = "/run/failover/release";
+
+/// Whether a SIGUSR1 should release the instance called `name`, given the
+/// contents of [`RELEASE_FILE`] (`None` if it couldn't be read).
+pub(crate) fn release_requested(list: Option<&str>, name: &str) -> bool {
+    let mut names = list
+        .into_iter()
+        .flat_map(str::lines)
+        .map(str::trim)
+        .filter(|line| !line.is_empty())
+        .peekable();
+    names.peek().is_none() || names.any(|listed| listed == name)
+}
+
 #[cfg(test)]
 mod tests {
     use std::num::NonZeroI32;
@@ -494,7 +1062,7 @@ mod tests {
 
     // Golden values below pin down the current hash/naming scheme. If this
     // test starts failing after an intentional change to `fnv1a_hash` or
-    // `mac_vlan_name`, remember that upgrades won't find the mac-vlan
+    // `vrrp_link_name`, remember that upgrades won't find the mac-vlan
     // interface a prior run created under the old name -- that's the actual
     // failure mode being guarded against, not just this assertion.
     #[test]
@@ -509,34 +1077,34 @@ mod tests {
     }
 
     #[test]
-    fn mac_vlan_name_is_stable_and_deterministic() {
-        let first = mac_vlan_name("eth0", 51, AddressFamily::V4);
-        let second = mac_vlan_name("eth0", 51, AddressFamily::V4);
+    fn vrrp_link_name_is_stable_and_deterministic() {
+        let first = vrrp_link_name("eth0", 51, AddressFamily::V4);
+        let second = vrrp_link_name("eth0", 51, AddressFamily::V4);
         assert_eq!(first, second);
         assert_eq!(first, "fover4-51-9724");
     }
 
     #[test]
-    fn mac_vlan_name_varies_with_vrid_parent_and_family() {
+    fn vrrp_link_name_varies_with_vrid_parent_and_family() {
         assert_ne!(
-            mac_vlan_name("eth0", 51, AddressFamily::V4),
-            mac_vlan_name("eth0", 52, AddressFamily::V4)
+            vrrp_link_name("eth0", 51, AddressFamily::V4),
+            vrrp_link_name("eth0", 52, AddressFamily::V4)
         );
         assert_ne!(
-            mac_vlan_name("eth0", 51, AddressFamily::V4),
-            mac_vlan_name("eth1", 51, AddressFamily::V4)
+            vrrp_link_name("eth0", 51, AddressFamily::V4),
+            vrrp_link_name("eth1", 51, AddressFamily::V4)
         );
         assert_ne!(
-            mac_vlan_name("eth0", 51, AddressFamily::V4),
-            mac_vlan_name("eth0", 51, AddressFamily::V6)
+            vrrp_link_name("eth0", 51, AddressFamily::V4),
+            vrrp_link_name("eth0", 51, AddressFamily::V6)
         );
     }
 
     #[test]
-    fn mac_vlan_name_fits_within_ifnamsiz() {
+    fn vrrp_link_name_fits_within_ifnamsiz() {
         // Worst case: 3-digit vrid, either family prefix.
-        let v4 = mac_vlan_name("eth0", 255, AddressFamily::V4);
-        let v6 = mac_vlan_name("eth0", 255, AddressFamily::V6);
+        let v4 = vrrp_link_name("eth0", 255, AddressFamily::V4);
+        let v6 = vrrp_link_name("eth0", 255, AddressFamily::V6);
         assert!(v4.len() <= 15, "{v4} is {} chars", v4.len());
         assert!(v6.len() <= 15, "{v6} is {} chars", v6.len());
     }
@@ -546,8 +1114,8 @@ mod tests {
         // v2 and v3 instances with the same name/vrid/parent are expected
         // to share the v4 mac-vlan -- naming is keyed by
         // (parent, vrid, family) only, version plays no part in it.
-        let a = mac_vlan_name("eth0", 51, AddressFamily::V4);
-        let b = mac_vlan_name("eth0", 51, AddressFamily::V4);
+        let a = vrrp_link_name("eth0", 51, AddressFamily::V4);
+        let b = vrrp_link_name("eth0", 51, AddressFamily::V4);
         assert_eq!(a, b);
     }
 
@@ -563,6 +1131,217 @@ mod tests {
         );
     }
 
+    fn interface_with(ips: &[IpAddr]) -> NetworkInterface {
+        NetworkInterface {
+            name: "eth0".to_string(),
+            description: String::new(),
+            index: 2,
+            mac: None,
+            ips: ips.iter().map(|ip| (*ip).into()).collect(),
+            flags: 0,
+        }
+    }
+
+    #[test]
+    fn owns_addresses_when_every_vip_is_on_the_interface() {
+        let v4: IpAddr = "192.168.100.100".parse().unwrap();
+        let v6: IpAddr = "fd00::1".parse().unwrap();
+        let iface = interface_with(&[v4, v6, "192.168.100.1".parse().unwrap()]);
+
+        assert!(owns_addresses(&iface, &[v4, v6]));
+    }
+
+    #[test]
+    fn does_not_own_addresses_when_any_vip_is_missing() {
+        let v4: IpAddr = "192.168.100.100".parse().unwrap();
+        let iface = interface_with(&[v4]);
+
+        assert!(!owns_addresses(&iface, &[v4, "fd00::1".parse().unwrap()]));
+        assert!(!owns_addresses(&iface, &[]));
+    }
+
+    #[test]
+    fn addresses_on_lists_the_vips_already_on_the_interface() {
+        let v4: IpAddr = "192.168.100.100".parse().unwrap();
+        let v6: IpAddr = "fd00::1".parse().unwrap();
+        let iface = interface_with(&[v4, "192.168.100.1".parse().unwrap()]);
+
+        assert_eq!(addresses_on(&iface, &[v4, v6]), vec![v4]);
+        assert!(addresses_on(&iface, &[v6]).is_empty());
+    }
+
+    fn link_with_flags(name: &str, flags: LinkFlags) -> LinkMessage {
+        let mut link = LinkMessage::default();
+        link.header.flags = flags;
+        link.attributes
+            .push(LinkAttribute::IfName(name.to_string()));
+        link
+    }
+
+    #[test]
+    fn link_carrier_needs_both_admin_up_and_lower_up() {
+        let up = link_with_flags("eth1", LinkFlags::Up | LinkFlags::LowerUp);
+        assert_eq!(link_carrier(&up), Some(("eth1", true)));
+
+        let no_carrier = link_with_flags("eth1", LinkFlags::Up);
+        assert_eq!(link_carrier(&no_carrier), Some(("eth1", false)));
+
+        let admin_down = link_with_flags("eth1", LinkFlags::LowerUp);
+        assert_eq!(link_carrier(&admin_down), Some(("eth1", false)));
+    }
+
+    #[test]
+    fn link_carrier_needs_a_name() {
+        assert!(link_carrier(&LinkMessage::default()).is_none());
+    }
+
+    fn link_with_info(info: Vec<LinkInfo>) -> LinkMessage {
+        let mut link = LinkMessage::default();
+        link.attributes.push(LinkAttribute::LinkInfo(info));
+        link
+    }
+
+    #[test]
+    fn ip_vlan_link_reports_its_kind_and_mode() {
+        let link = link_with_info(vec![
+            LinkInfo::Kind(InfoKind::IpVlan),
+            LinkInfo::Data(InfoData::IpVlan(vec![InfoIpVlan::Mode(
+                IpVlanMode::L2,
+            )])),
+        ]);
+
+        assert!(link_is_ip_vlan(&link));
+        assert!(!link_is_mac_vlan(&link));
+        assert_eq!(link_ip_vlan_mode(&link), Some(IpVlanMode::L2));
+    }
+
+    #[test]
+    fn ip_vlan_link_without_data_has_no_mode() {
+        let link = link_with_info(vec![LinkInfo::Kind(InfoKind::IpVlan)]);
+
+        assert!(link_is_ip_vlan(&link));
+        assert_eq!(link_ip_vlan_mode(&link), None);
+    }
+
+    #[test]
+    fn other_links_are_not_ip_vlans() {
+        let mac_vlan = link_with_info(vec![LinkInfo::Kind(InfoKind::MacVlan)]);
+        assert!(!link_is_ip_vlan(&mac_vlan));
+        assert_eq!(link_ip_vlan_mode(&mac_vlan), None);
+
+        let plain = link_with_flags("eth0", LinkFlags::Up);
+        assert!(!link_is_ip_vlan(&plain));
+        assert_eq!(link_ip_vlan_mode(&plain), None);
+    }
+
+    #[test]
+    fn route_prefix_reads_destination_and_table() {
+        let route = RouteMessageBuilder::<Ipv4Addr>::new()
+            .destination_prefix(Ipv4Addr::new(10, 0, 0, 0), 8)
+            .table_id(100)
+            .build();
+
+        assert_eq!(
+            route_prefix(&route),
+            Some(("10.0.0.0/8".parse().unwrap(), 100))
+        );
+    }
+
+    #[test]
+    fn route_prefix_without_destination_is_a_default_route() {
+        let v4 = RouteMessageBuilder::<Ipv4Addr>::new().build();
+        let v6 = RouteMessageBuilder::<Ipv6Addr>::new().build();
+
+        assert_eq!(
+            route_prefix(&v4),
+            Some(("0.0.0.0/0".parse().unwrap(), 254))
+        );
+        assert_eq!(route_prefix(&v6), Some(("::/0".parse().unwrap(), 254)));
+    }
+
+    #[test]
+    fn accept_mode_table_is_unique_per_version_and_vrid() {
+        assert_eq!(accept_mode_table(VrrpVersion::V3, 51), "failover_v3_51");
+        assert_ne!(
+            accept_mode_table(VrrpVersion::V2, 51),
+            accept_mode_table(VrrpVersion::V3, 51)
+        );
+    }
+
+    #[test]
+    fn accept_mode_script_drops_both_families_on_add() {
+        let v4 = [Ipv4Addr::new(192, 168, 100, 100)];
+        let v6 = ["fd00::1".parse().unwrap()];
+        let script =
+            accept_mode_script(&AddressAction::Add, "failover_v3_51", &v4, &v6);
+
+        assert!(script.starts_with(
+            "table inet failover_v3_51\ndelete table inet failover_v3_51\n"
+        ));
+        assert!(script.contains("ip daddr { 192.168.100.100 } drop"));
+        assert!(script.contains("ip6 daddr { fd00::1 } drop"));
+    }
+
+    #[test]
+    fn accept_mode_script_only_removes_the_table_on_delete() {
+        let v4 = [Ipv4Addr::new(192, 168, 100, 100)];
+        let script = accept_mode_script(
+            &AddressAction::Delete,
+            "failover_v2_51",
+            &v4,
+            &[],
+        );
+
+        assert_eq!(
+            script,
+            "table inet failover_v2_51\ndelete table inet failover_v2_51\n"
+        );
+    }
+
+    #[test]
+    fn virtual_mac_discard_drops_frames_to_the_mac_on_its_link() {
+        let link = "fover4-51-abcd";
+        let table = virtual_mac_discard_table(link);
+        let mac = AddressFamily::V4.virtual_mac(51);
+
+        let script = virtual_mac_discard_script(true, &table, link, mac);
+        assert_eq!(table, "failover_discard_fover4_51_abcd");
+        assert!(script.starts_with(
+            "table netdev failover_discard_fover4_51_abcd\ndelete table netdev"
+        ));
+        assert!(script.contains("hook ingress device \"fover4-51-abcd\""));
+        assert!(script.contains("ether daddr 00:00:5e:00:01:33 drop"));
+
+        let script = virtual_mac_discard_script(false, &table, link, mac);
+        assert!(!script.contains("drop"));
+    }
+
+    #[test]
+    fn shared_mac_vlan_only_discards_while_neither_version_is_master() {
+        let mut users = VirtualMacUsers::default();
+        assert_eq!(users.update(2, false), Some(true));
+        users.discarding = Some(true);
+        assert_eq!(users.update(3, false), None);
+
+        // The v3 sibling becoming MASTER lifts it, and the v2 instance
+        // dropping back to BACKUP mustn't put it back.
+        assert_eq!(users.update(3, true), Some(false));
+        users.discarding = Some(false);
+        assert_eq!(users.update(2, false), None);
+
+        // Nor its leaving, until the last instance is gone.
+        assert_eq!(users.leave(3), Some(true));
+        users.discarding = Some(true);
+        assert_eq!(users.leave(2), Some(false));
+    }
+
+    #[test]
+    fn discard_filter_is_always_set_on_first_use() {
+        // One may be left over from an earlier run.
+        let mut users = VirtualMacUsers::default();
+        assert_eq!(users.update(3, true), Some(false));
+    }
+
     fn netlink_error_with_code(code: i32) -> rtnetlink::Error {
         let mut msg = ErrorMessage::default();
         msg.code = NonZeroI32::new(code);
@@ -586,4 +1365,21 @@ mod tests {
         let err = rtnetlink::Error::RequestFailed;
         assert!(!is_no_such_device(&err));
     }
+
+    #[test]
+    fn release_without_a_list_releases_every_instance() {
+        assert!(release_requested(None, "vr1"));
+        assert!(release_requested(Some(""), "vr1"));
+        assert!(release_requested(Some(" \n\n"), "vr1"));
+    }
+
+    #[test]
+    fn release_with_a_list_only_releases_the_named_instances() {
+        let list = Some("vr1\n  vr3 \n");
+
+        assert!(release_requested(list, "vr1"));
+        assert!(release_requested(list, "vr3"));
+        assert!(!release_requested(list, "vr2"));
+        assert!(!release_requested(list, "vr"));
+    }
 }
@@ -1,26 +1,34 @@
-use std::net::{Ipv4Addr, Ipv6Addr};
-use std::sync::{Arc, Mutex};
+use std::net::{IpAddr, Ipv4Addr, Ipv6Addr};
+use std::sync::Arc;
 
+use actor::{Input, RouterHandle};
 use error::{FailoverError, NetworkError};
 use general::AddressFamily;
-use observer::EventObserver;
 use pnet::datalink::NetworkInterface;
 use router::VirtualRouter;
 use serde::{Deserialize, Deserializer, Serialize, Serializer};
 use state_machine::Event;
 use tokio::signal;
+use tokio::sync::oneshot;
 use tokio::task::JoinSet;
 
+mod actor;
+mod announce;
 pub mod config;
 mod core_tasks;
+mod dispatch;
 pub mod error;
 pub mod general;
+mod links;
 mod network;
-mod observer;
 mod packet;
 mod pkt;
 pub mod router;
+#[cfg(test)]
+mod sim;
 mod state_machine;
+mod sync;
+mod track;
 
 #[derive(Clone, Copy, Debug, PartialEq, Eq, Default)]
 pub(crate) enum VrrpVersion {
@@ -103,7 +111,10 @@ pub(crate) type ConfigResult<T> = Result<T, error::ConfigError>;
 
 #[derive(Clone)]
 pub(crate) struct TaskItems {
-    vrouter: Arc<Mutex<VirtualRouter>>,
+    router: RouterHandle,
+    /// The router as it was handed to its task, for the settings the other
+    /// tasks need; its state is never updated.
+    settings: Arc<VirtualRouter>,
     interface: NetworkInterface,
     interface_v6: Option<NetworkInterface>,
     parent_interface: NetworkInterface,
@@ -127,45 +138,99 @@ impl std::fmt::Display for AddressAction {
 /// initiates the VRRP functions across the board.
 /// from interfaces, channels, packet handling etc...
 pub async fn run(mut vrouter: VirtualRouter) -> Result<(), FailoverError> {
+    links::start().await?;
     let parent_interface = general::get_interface(&vrouter.network_interface)?;
     vrouter.primary_ip = general::primary_ipv4(&parent_interface)?;
 
-    vrouter.mac_vlan_interface_v4 = general::create_mac_vlan(
-        &parent_interface.name,
-        vrouter.vrid,
-        AddressFamily::V4,
-    )
-    .await?;
-    let interface = general::get_interface(&vrouter.mac_vlan_interface_v4)?;
+    let vips: Vec<IpAddr> = vrouter
+        .ipv4_addrs()
+        .into_iter()
+        .map(IpAddr::V4)
+        .chain(vrouter.ipv6_addrs().into_iter().map(IpAddr::V6))
+        .collect();
+    if general::owns_addresses(&parent_interface, &vips) {
+        vrouter.set_address_owner();
+        log::info!(
+            "({}) virtual address(es) found on {}; running as address owner (priority 255)",
+            vrouter.name,
+            parent_interface.name
+        );
+        if vrouter.owner_preempts_at_once() {
+            return Err(NetworkError::OwnerPreemptsAtOnce {
+                name: vrouter.name.clone(),
+            }
+            .into());
+        }
+    } else if vrouter.priority == 255 {
+        return Err(NetworkError::NotAddressOwner {
+            name: vrouter.name.clone(),
+            interface: parent_interface.name.clone(),
+        }
+        .into());
+    }
 
-    let interface_v6 = if vrouter.version == VrrpVersion::V3 {
-        let v6_name = general::create_mac_vlan(
+    // Without a virtual MAC both families run straight on the parent.
+    let (interface, interface_v6) = if !vrouter.use_virtual_mac {
+        vrouter.mac_vlan_interface_v4 = parent_interface.name.clone();
+        vrouter.host_addresses =
+            general::addresses_on(&parent_interface, &vips);
+        if !vrouter.host_addresses.is_empty() && !vrouter.is_address_owner() {
+            log::info!(
+                "({}) {:?} already on {}; leaving it to the host",
+                vrouter.name,
+                vrouter.host_addresses,
+                parent_interface.name
+            );
+        }
+        let interface_v6 = if vrouter.version == VrrpVersion::V3 {
+            vrouter.mac_vlan_interface_v6 = Some(parent_interface.name.clone());
+            vrouter.primary_ip_v6 = general::primary_ipv6(&parent_interface);
+            Some(parent_interface.clone())
+        } else {
+            None
+        };
+        log::info!(
+            "({}) use_virtual_mac off: running directly on {}",
+            vrouter.name,
+            parent_interface.name
+        );
+        (parent_interface.clone(), interface_v6)
+    } else {
+        vrouter.mac_vlan_interface_v4 = general::create_link(
+            vrouter.link_backend,
             &parent_interface.name,
             vrouter.vrid,
-            AddressFamily::V6,
+            AddressFamily::V4,
         )
         .await?;
-        vrouter.mac_vlan_interface_v6 = Some(v6_name.clone());
-        vrouter.primary_ip_v6 = general::primary_ipv6(&parent_interface);
-        Some(general::get_interface(&v6_name)?)
-    } else {
-        None
+        let interface = general::get_interface(&vrouter.mac_vlan_interface_v4)?;
+
+        let interface_v6 = if vrouter.version == VrrpVersion::V3 {
+            let v6_name = general::create_link(
+                vrouter.link_backend,
+                &parent_interface.name,
+                vrouter.vrid,
+                AddressFamily::V6,
+            )
+            .await?;
+            vrouter.mac_vlan_interface_v6 = Some(v6_name.clone());
+            vrouter.primary_ip_v6 = general::primary_ipv6(&parent_interface);
+            Some(general::get_interface(&v6_name)?)
+        } else {
+            None
+        };
+        (interface, interface_v6)
     };
 
+    let settings = Arc::new(vrouter.clone());
+    let (router, router_task) = actor::spawn(vrouter);
     let items = TaskItems {
-        vrouter: Arc::new(Mutex::new(vrouter)),
+        router,
+        settings,
         interface,
         interface_v6,
         parent_interface,
     };
-
-    match EventObserver::notify(items.vrouter.clone(), Event::Startup) {
-        Ok(_) => {}
-        Err(err) => {
-            log::error!("{err}");
-            panic!("Problem running initial notify statement");
-        }
-    };
     let mut tasks_set = JoinSet::new();
 
     // Listens for incoming VRRP advertisements.
@@ -187,31 +252,94 @@ pub async fn run(mut vrouter: VirtualRouter) -> Result<(), FailoverError> {
         tasks_set.spawn(async { core_tasks::ndp_process(ndp_items).await });
     }
 
-    let timer_items = items.clone();
-    tasks_set.spawn(async { core_tasks::timer_process(timer_items).await });
+    // Watches carrier on `track_interfaces`.
+    let track_items = items.clone();
+    let (ready, interfaces_ready) = oneshot::channel();
+    tasks_set.spawn(async {
+        core_tasks::track_interfaces_process(track_items, ready).await
+    });
+
+    // Runs `track_scripts` health checks.
+    let script_items = items.clone();
+    let (ready, scripts_ready) = oneshot::channel();
+    tasks_set.spawn(async {
+        core_tasks::track_scripts_process(script_items, ready).await
+    });
+
+    // Watches `track_routes` in the kernel routing tables.
+    let route_items = items.clone();
+    let (ready, routes_ready) = oneshot::channel();
+    tasks_set.spawn(async {
+        core_tasks::track_routes_process(route_items, ready).await
+    });
+
+    // Starts the router once every tracker has reported in, so one whose
+    // uplink is already down goes straight to FAULT rather than through
+    // MASTER.
+    let router = items.router.clone();
+    tasks_set.spawn(async move {
+        for ready in [interfaces_ready, scripts_ready, routes_ready] {
+            let _ = ready.await;
+        }
+        router.send(Input::Event(Event::Startup)).await;
+        Ok(())
+    });
+
+    // Follows the rest of this instance's sync group.
+    let sync_items = items.clone();
+    tasks_set.spawn(async { core_tasks::sync_group_process(sync_items).await });
+
+    // Sends gratuitous ARPs/unsolicited NAs whenever this instance becomes
+    // MASTER, and refreshes them while it stays MASTER.
+    let announce_items = items.clone();
+    tasks_set
+        .spawn(async { core_tasks::announce_process(announce_items).await });
 
     // Wait for either a graceful shutdown signal, or all of the tasks above
     // finishing on their own (e.g. an unrecoverable bind error) -- whichever
-    // happens first. Every VirtualRouter's `run()` registers its own signal
-    // listeners independently; tokio fans a single incoming signal out to
-    // all of them, so each session cleans up only its own mac-vlan.
+    // happens first. SIGUSR1 releases mastership without shutting down --
+    // on every instance, or only on those named in `general::RELEASE_FILE`
+    // -- and SIGUSR2 logs each instance's status.
+    // Every VirtualRouter's `run()` registers its own signal listeners
+    // independently; tokio fans a single incoming signal out to all of
+    // them, so each session cleans up (or releases) only its own router.
     let mut sigterm =
         signal::unix::signal(signal::unix::SignalKind::terminate())
             .map_err(NetworkError::SignalHandler)?;
+    let mut sigusr1 =
+        signal::unix::signal(signal::unix::SignalKind::user_defined1())
+            .map_err(NetworkError::SignalHandler)?;
+    let mut sigusr2 =
+        signal::unix::signal(signal::unix::SignalKind::user_defined2())
+            .map_err(NetworkError::SignalHandler)?;
 
-    tokio::select! {
-        _ = signal::ctrl_c() => {
-            log::info!("({}) received SIGINT, shutting down", items.interface.name);
+    loop {
+        tokio::select! {
+            _ = signal::ctrl_c() => {
+                log::info!("({}) received SIGINT, shutting down", items.interface.name);
+                break;
+            }
+            _ = sigterm.recv() => {
+                log::info!("({}) received SIGTERM, shutting down", items.interface.name);
+                break;
+            }
+            _ = sigusr1.recv() => {
+                let list = tokio::fs::read_to_string(general::RELEASE_FILE)
+                    .await
+                    .ok();
+                if general::release_requested(list.as_deref(), &items.settings.name) {
+                    log::info!("({}) received SIGUSR1, releasing mastership", items.interface.name);
+                    items.router.send(Input::Event(Event::Release)).await;
+                }
+            }
+            _ = sigusr2.recv() => items.router.send(Input::Status).await,
+            _ = async { while tasks_set.join_next().await.is_some() {} } => break,
         }
-        _ = sigterm.recv() => {
-            log::info!("({}) received SIGTERM, shutting down", items.interface.name);
-        }
-        _ = async { while tasks_set.join_next().await.is_some() {} } => {}
     }
 
-    if let Err(err) =
-        EventObserver::notify(items.vrouter.clone(), Event::Shutdown)
-    {
+    // The router's task finishes once it has torn down.
+    items.router.send(Input::Event(Event::Shutdown)).await;
+    if let Err(err) = router_task.await {
         log::error!("Problem tearing down virtual router: {err}");
     }
 
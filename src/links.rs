@@ -0,0 +1,258 @@
+//! In-process cache of the host's links and addresses, filled from an
+//! rtnetlink dump and kept current by link/address notifications, so the
+//! packet hot paths can look up an interface's MAC or check for one of our
+//! own addresses without a syscall.
+use std::collections::{HashMap, HashSet};
+use std::net::IpAddr;
+use std::sync::{LazyLock, PoisonError, RwLock};
+
+use futures_util::{StreamExt, TryStreamExt};
+use netlink_packet_core::NetlinkPayload;
+use netlink_packet_route::RouteNetlinkMessage;
+use netlink_packet_route::address::{AddressAttribute, AddressMessage};
+use netlink_packet_route::link::LinkMessage;
+use rtnetlink::{MulticastGroup, new_multicast_connection};
+use tokio::sync::OnceCell;
+
+use crate::error::NetworkError;
+use crate::{NetResult, general};
+
+static CACHE: LazyLock<RwLock<LinkCache>> =
+    LazyLock::new(|| RwLock::new(LinkCache::default()));
+static STARTED: OnceCell<()> = OnceCell::const_new();
+
+#[derive(Debug, Default)]
+struct LinkCache {
+    /// Link index -> (name, MAC).
+    links: HashMap<u32, (String, Option<[u8; 6]>)>,
+    by_name: HashMap<String, u32>,
+    /// Every address on the host -> the link(s) it's on.
+    addresses: HashMap<IpAddr, HashSet<u32>>,
+}
+
+impl LinkCache {
+    fn apply(&mut self, message: &RouteNetlinkMessage) {
+        match message {
+            RouteNetlinkMessage::NewLink(link) => self.new_link(link),
+            RouteNetlinkMessage::DelLink(link) => {
+                self.del_link(link.header.index)
+            }
+            RouteNetlinkMessage::NewAddress(msg) => {
+                if let Some(addr) = address(msg) {
+                    self.addresses
+                        .entry(addr)
+                        .or_default()
+                        .insert(msg.header.index);
+                }
+            }
+            RouteNetlinkMessage::DelAddress(msg) => {
+                if let Some(addr) = address(msg) {
+                    self.del_address(addr, msg.header.index);
+                }
+            }
+            _ => {}
+        }
+    }
+
+    fn new_link(&mut self, link: &LinkMessage) {
+        let Some(name) = general::link_name(link) else {
+            return;
+        };
+        let index = link.header.index;
+        let mac = general::link_mac_address(link)
+            .and_then(|mac| <[u8; 6]>::try_from(mac.as_slice()).ok());
+        // Also covers a rename.
+        if let Some((old_name, _)) = self.links.get(&index) {
+            self.by_name.remove(old_name);
+        }
+        self.by_name.insert(name.to_string(), index);
+        self.links.insert(index, (name.to_string(), mac));
+    }
+
+    fn del_link(&mut self, index: u32) {
+        if let Some((name, _)) = self.links.remove(&index) {
+            self.by_name.remove(&name);
+        }
+        self.addresses.retain(|_, links| {
+            links.remove(&index);
+            !links.is_empty()
+        });
+    }
+
+    fn del_address(&mut self, addr: IpAddr, index: u32) {
+        if let Some(links) = self.addresses.get_mut(&addr) {
+            links.remove(&index);
+            if links.is_empty() {
+                self.addresses.remove(&addr);
+            }
+        }
+    }
+
+    fn index(&self, name: &str) -> Option<u32> {
+        self.by_name.get(name).copied()
+    }
+
+    fn mac(&self, name: &str) -> Option<[u8; 6]> {
+        let index = self.by_name.get(name)?;
+        self.links.get(index)?.1
+    }
+}
+
+/// The local address in an address message: `IFA_LOCAL` where there is
+/// one (it differs from `IFA_ADDRESS` on point-to-point links), otherwise
+/// `IFA_ADDRESS`.
+fn address(msg: &AddressMessage) -> Option<IpAddr> {
+    let mut address = None;
+    for attr in &msg.attributes {
+        match attr {
+            AddressAttribute::Local(addr) => return Some(*addr),
+            AddressAttribute::Address(addr) => address = Some(*addr),
+            _ => {}
+        }
+    }
+    address
+}
+
+/// Fills the cache and starts keeping it current, the first time it's
+/// called; later calls return once that's done.
+pub(crate) async fn start() -> NetResult<()> {
+    STARTED.get_or_try_init(fill_and_watch).await?;
+    Ok(())
+}
+
+async fn fill_and_watch() -> NetResult<()> {
+    // Subscribed before dumping, so nothing that changes in between is
+    // missed.
+    let (connection, handle, mut messages) = new_multicast_connection(&[
+        MulticastGroup::Link,
+        MulticastGroup::Ipv4Ifaddr,
+        MulticastGroup::Ipv6Ifaddr,
+    ])
+    .map_err(NetworkError::NetlinkConnect)?;
+    tokio::spawn(connection);
+
+    let mut dumped = vec![];
+    let mut links = handle.link().get().execute();
+    while let Some(link) =
+        links.try_next().await.map_err(NetworkError::LinkDump)?
+    {
+        dumped.push(RouteNetlinkMessage::NewLink(link));
+    }
+    let mut addresses = handle.address().get().execute();
+    while let Some(msg) =
+        addresses.try_next().await.map_err(NetworkError::LinkDump)?
+    {
+        dumped.push(RouteNetlinkMessage::NewAddress(msg));
+    }
+    {
+        let mut cache = CACHE.write().unwrap_or_else(PoisonError::into_inner);
+        for message in &dumped {
+            cache.apply(message);
+        }
+    }
+
+    tokio::spawn(async move {
+        while let Some((message, _)) = messages.next().await {
+            if let NetlinkPayload::InnerMessage(message) = message.payload {
+                CACHE
+                    .write()
+                    .unwrap_or_else(PoisonError::into_inner)
+                    .apply(&message);
+            }
+        }
+        log::error!("Link/address notifications stopped; cache is stale");
+    });
+    Ok(())
+}
+
+/// Index of the interface called `name`; changes if it's recreated.
+pub(crate) fn index(name: &str) -> Option<u32> {
+    CACHE
+        .read()
+        .unwrap_or_else(PoisonError::into_inner)
+        .index(name)
+}
+
+/// MAC address of the interface called `name`.
+pub(crate) fn mac(name: &str) -> Option<[u8; 6]> {
+    CACHE
+        .read()
+        .unwrap_or_else(PoisonError::into_inner)
+        .mac(name)
+}
+
+/// Whether `addr` is assigned to any interface on this host.
+pub(crate) fn is_local_address(addr: IpAddr) -> bool {
+    CACHE
+        .read()
+        .unwrap_or_else(PoisonError::into_inner)
+        .addresses
+        .contains_key(&addr)
+}
+
+#[cfg(test)]
+mod tests {
+    use netlink_packet_route::link::LinkAttribute;
+
+    use super::*;
+
+    fn link(index: u32, name: &str, mac: [u8; 6]) -> RouteNetlinkMessage {
+        let mut link = LinkMessage::default();
+        link.header.index = index;
+        link.attributes
+            .push(LinkAttribute::IfName(name.to_string()));
+        link.attributes.push(LinkAttribute::Address(mac.to_vec()));
+        RouteNetlinkMessage::NewLink(link)
+    }
+
+    fn address_msg(index: u32, addr: &str) -> AddressMessage {
+        let mut msg = AddressMessage::default();
+        msg.header.index = index;
+        msg.attributes
+            .push(AddressAttribute::Address(addr.parse().unwrap()));
+        msg
+    }
+
+    #[test]
+    fn links_are_looked_up_by_name_and_follow_renames() {
+        let mut cache = LinkCache::default();
+        let mac = [0x00, 0x00, 0x5e, 0x00, 0x01, 51];
+        cache.apply(&link(3, "eth0", mac));
+        assert_eq!(cache.mac("eth0"), Some(mac));
+
+        cache.apply(&link(3, "wan0", mac));
+        assert_eq!(cache.mac("eth0"), None);
+        assert_eq!(cache.mac("wan0"), Some(mac));
+    }
+
+    #[test]
+    fn addresses_stay_local_while_any_link_has_them() {
+        let mut cache = LinkCache::default();
+        let addr: IpAddr = "192.168.100.10".parse().unwrap();
+        cache.apply(&RouteNetlinkMessage::NewAddress(address_msg(
+            3,
+            "192.168.100.10",
+        )));
+        cache.apply(&RouteNetlinkMessage::NewAddress(address_msg(
+            4,
+            "192.168.100.10",
+        )));
+
+        cache.apply(&RouteNetlinkMessage::DelAddress(address_msg(
+            3,
+            "192.168.100.10",
+        )));
+        assert!(cache.addresses.contains_key(&addr));
+
+        cache.del_link(4);
+        assert!(!cache.addresses.contains_key(&addr));
+    }
+
+    #[test]
+    fn local_takes_precedence_over_the_peer_address() {
+        let mut msg = address_msg(3, "10.0.0.2");
+        msg.attributes
+            .push(AddressAttribute::Local("10.0.0.1".parse().unwrap()));
+        assert_eq!(address(&msg), Some("10.0.0.1".parse().unwrap()));
+    }
+}
@@ -1,8 +1,10 @@
+use std::collections::HashMap;
 use std::ffi::CString;
 use std::io;
 use std::mem::MaybeUninit;
 use std::net::{IpAddr, Ipv4Addr, Ipv6Addr, SocketAddrV4, SocketAddrV6};
 use std::os::fd::AsRawFd;
+use std::sync::{LazyLock, Mutex, PoisonError};
 
 use libc::{AF_PACKET, PACKET_OUTGOING, c_void, sendto, sockaddr, sockaddr_ll};
 use socket2::{
@@ -10,16 +12,28 @@ use socket2::{
 };
 use tokio::io::unix::AsyncFd;
 
+use crate::links;
 use crate::packet::{
     ALL_NODES_V6_MCAST_ADDR, ARPframe, NdpNeighborAdvertisement,
-    VRRP_V6_MCAST_ADDR, VrrpPacket,
+    VRRP_MCAST_ADDR, VRRP_V6_MCAST_ADDR, VrrpPacket,
 };
 
 // IANA-assigned IP protocol numbers.
 const VRRP_PROTOCOL_NUMBER: i32 = 112;
 const ICMPV6_PROTOCOL_NUMBER: i32 = 58;
 
-const VRRP_MCAST_ADDR: Ipv4Addr = Ipv4Addr::new(224, 0, 0, 18);
+/// `ICMP6_FILTER` from `<netinet/icmp6.h>`, which libc doesn't export.
+const ICMP6_FILTER: libc::c_int = 1;
+
+/// A classic BPF program accepting nothing, for the VRRP send sockets: as
+/// raw protocol-112 sockets they'd otherwise be handed a copy of every
+/// advert, which nothing reads.
+const DROP_ALL: [libc::sock_filter; 1] = [libc::sock_filter {
+    code: (libc::BPF_RET | libc::BPF_K) as u16,
+    jt: 0,
+    jf: 0,
+    k: 0,
+}];
 
 fn if_index(ifname: &str) -> io::Result<u32> {
     let c_ifname = CString::new(ifname)
@@ -47,96 +61,213 @@ fn arp_link_addr(ifindex: u32) -> SockAddr {
     }
 }
 
-/// Sends a single VRRP advertisement to the VRRP multicast group
-/// (224.0.0.18) over `ifname`, sourced from `src_ip`.
-///
-/// Opens a fresh socket per call: VRRP advertisements are infrequent (once
-/// per advertisement interval, typically ~1s), so the overhead of a
-/// throwaway socket is negligible next to the simplicity it buys.
-/// `ifname` is the name of the mac-vlan interface.
+/// What a cached send socket is for, and so how it's configured. VRRP
+/// sockets are bound to their source address as well as the interface.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+enum SendKind {
+    Vrrp4(Ipv4Addr),
+    Vrrp6(Ipv6Addr),
+    Arp,
+    Ndp,
+}
+
+struct SendSocket {
+    /// Index of the interface when the socket was bound to it; a new one
+    /// means the interface was recreated and the socket is stale.
+    ifindex: u32,
+    sock: Socket,
+}
+
+type SendSockets = HashMap<(String, SendKind), SendSocket>;
+
+/// Send sockets kept open across sends, per interface (and VRRP source
+/// address): adverts go out every advert interval, for every instance.
+static SEND_SOCKETS: LazyLock<Mutex<SendSockets>> =
+    LazyLock::new(|| Mutex::new(HashMap::new()));
+
+/// Opens the `kind` send socket for `ifname`. None of them is ever read,
+/// so each is set up to receive nothing rather than fill its buffer.
+fn open_send_socket(kind: SendKind, ifname: &str) -> io::Result<Socket> {
+    let sock = match kind {
+        SendKind::Vrrp4(src_ip) => {
+            let sock = Socket::new(
+                Domain::IPV4,
+                Type::RAW,
+                Some(Protocol::from(VRRP_PROTOCOL_NUMBER)),
+            )?;
+            sock.bind_device(Some(ifname.as_bytes()))?;
+            sock.bind(&SocketAddrV4::new(src_ip, 0).into())?;
+            sock.set_ttl(255)?;
+            sock.set_multicast_ttl_v4(255)?;
+            sock.attach_filter(&DROP_ALL)?;
+            sock
+        }
+        SendKind::Vrrp6(src_ip) => {
+            let sock = Socket::new(
+                Domain::IPV6,
+                Type::RAW,
+                Some(Protocol::from(VRRP_PROTOCOL_NUMBER)),
+            )?;
+            sock.bind_device(Some(ifname.as_bytes()))?;
+            sock.bind(&SocketAddrV6::new(src_ip, 0, 0, 0).into())?;
+            sock.set_unicast_hops_v6(255)?;
+            sock.set_multicast_hops_v6(255)?;
+            sock.attach_filter(&DROP_ALL)?;
+            sock
+        }
+        SendKind::Arp => {
+            // Protocol 0: a packet socket that's only sent from needn't be
+            // handed every ARP frame. `arp_link_addr` sets the ethertype.
+            let sock = Socket::new(Domain::PACKET, Type::RAW, None)?;
+            sock.bind_device(Some(ifname.as_bytes()))?;
+            sock.set_broadcast(true)?;
+            sock
+        }
+        SendKind::Ndp => {
+            let sock = Socket::new(
+                Domain::IPV6,
+                Type::RAW,
+                Some(Protocol::from(ICMPV6_PROTOCOL_NUMBER)),
+            )?;
+            sock.bind_device(Some(ifname.as_bytes()))?;
+            block_all_icmpv6(&sock)?;
+            sock
+        }
+    };
+    Ok(sock)
+}
+
+/// Sets an `ICMP6_FILTER` blocking every ICMPv6 type on `sock`.
+fn block_all_icmpv6(sock: &Socket) -> io::Result<()> {
+    let filter = [u32::MAX; 8];
+    let ret = unsafe {
+        libc::setsockopt(
+            sock.as_raw_fd(),
+            libc::IPPROTO_ICMPV6,
+            ICMP6_FILTER,
+            filter.as_ptr().cast::<c_void>(),
+            size_of_val(&filter) as libc::socklen_t,
+        )
+    };
+    if ret < 0 {
+        return Err(io::Error::last_os_error());
+    }
+    Ok(())
+}
+
+/// Runs `send` on the cached `kind` socket for `ifname` (with the
+/// interface's index), opening it first if there's none yet or the
+/// interface has been recreated since. A socket that fails to send is
+/// dropped, so the next send starts afresh.
+fn with_send_socket<T>(
+    kind: SendKind,
+    ifname: &str,
+    send: impl FnOnce(&Socket, u32) -> io::Result<T>,
+) -> io::Result<T> {
+    let ifindex = match links::index(ifname) {
+        Some(ifindex) => ifindex,
+        None => if_index(ifname)?,
+    };
+    let mut sockets =
+        SEND_SOCKETS.lock().unwrap_or_else(PoisonError::into_inner);
+    with_cached_socket(
+        &mut sockets,
+        (ifname.to_string(), kind),
+        ifindex,
+        || open_send_socket(kind, ifname),
+        send,
+    )
+}
+
+/// The caching behind `with_send_socket`: runs `send` on `key`'s socket in
+/// `sockets`, first replacing it with a fresh one from `open` if there's
+/// none or it was bound to another `ifindex`, and drops it if `send` fails.
+fn with_cached_socket<T>(
+    sockets: &mut SendSockets,
+    key: (String, SendKind),
+    ifindex: u32,
+    open: impl FnOnce() -> io::Result<Socket>,
+    send: impl FnOnce(&Socket, u32) -> io::Result<T>,
+) -> io::Result<T> {
+    let cached = match sockets.get(&key) {
+        Some(cached) if cached.ifindex == ifindex => cached,
+        _ => {
+            let sock = open()?;
+            sockets.insert(key.clone(), SendSocket { ifindex, sock });
+            &sockets[&key]
+        }
+    };
+    let result = send(&cached.sock, ifindex);
+    if result.is_err() {
+        sockets.remove(&key);
+    }
+    result
+}
+
+/// Closes every cached send socket on `ifname`, e.g. once it's deleted.
+pub(crate) fn close_send_sockets(ifname: &str) {
+    SEND_SOCKETS
+        .lock()
+        .unwrap_or_else(PoisonError::into_inner)
+        .retain(|(name, _), _| name != ifname);
+}
+
+/// Sends a single VRRP advertisement to `dst_ip` -- the VRRP multicast
+/// group (224.0.0.18), or a unicast peer -- over `ifname`, sourced from
+/// `src_ip`. `ifname` is the name of the mac-vlan interface for
+/// multicast, or the parent interface for unicast.
 pub fn send_vrrp_packet_v4(
     ifname: &str,
     src_ip: Ipv4Addr,
+    dst_ip: Ipv4Addr,
     packet: VrrpPacket,
 ) -> io::Result<usize> {
-    let sock = Socket::new(
-        Domain::IPV4,
-        Type::RAW,
-        Some(Protocol::from(VRRP_PROTOCOL_NUMBER)),
-    )?;
-    sock.bind_device(Some(ifname.as_bytes()))?;
-    sock.bind(&SocketAddrV4::new(src_ip, 0).into())?;
-    sock.set_ttl(255)?;
-    sock.set_multicast_ttl_v4(255)?;
-
     let buf: &[u8] = &packet.encode(IpAddr::V4(src_ip));
-    let saddr = SocketAddrV4::new(VRRP_MCAST_ADDR, 0);
+    let saddr = SocketAddrV4::new(dst_ip, 0);
 
-    sock.send_to(buf, &saddr.into())
+    with_send_socket(SendKind::Vrrp4(src_ip), ifname, |sock, _| {
+        sock.send_to(buf, &saddr.into())
+    })
 }
 
-/// IPv6 counterpart of [`send_vrrp_packet_v4`]: sends to the VRRP IPv6
-/// multicast group (ff02::12) over `ifname`, sourced from `src_ip`.
+/// IPv6 counterpart of [`send_vrrp_packet_v4`]: sends to `dst_ip` -- the
+/// VRRP IPv6 multicast group (ff02::12), or a unicast peer -- over
+/// `ifname`, sourced from `src_ip`.
 pub fn send_vrrp_packet_v6(
     ifname: &str,
     src_ip: Ipv6Addr,
+    dst_ip: Ipv6Addr,
     packet: VrrpPacket,
 ) -> io::Result<usize> {
-    let sock = Socket::new(
-        Domain::IPV6,
-        Type::RAW,
-        Some(Protocol::from(VRRP_PROTOCOL_NUMBER)),
-    )?;
-    sock.bind_device(Some(ifname.as_bytes()))?;
-    sock.bind(&SocketAddrV6::new(src_ip, 0, 0, 0).into())?;
-    sock.set_unicast_hops_v6(255)?;
-    sock.set_multicast_hops_v6(255)?;
-
-    let buf: &[u8] = &packet.encode(IpAddr::V6(src_ip));
-    let saddr = SocketAddrV6::new(VRRP_V6_MCAST_ADDR, 0, 0, 0);
-
-    sock.send_to(buf, &saddr.into())
+    let buf: &[u8] = &packet.encode_to(IpAddr::V6(src_ip), IpAddr::V6(dst_ip));
+    let saddr = SocketAddrV6::new(dst_ip, 0, 0, 0);
+
+    with_send_socket(SendKind::Vrrp6(src_ip), ifname, |sock, _| {
+        sock.send_to(buf, &saddr.into())
+    })
 }
 
 /// Sends a single ARP frame over `ifname`.
 pub fn send_packet_arp(ifname: &str, arp_frame: ARPframe) {
-    let sock = match Socket::new(
-        Domain::PACKET,
-        Type::RAW,
-        Some(Protocol::from(i32::from((libc::ETH_P_ARP as u16).to_be()))),
-    ) {
-        Ok(sock) => sock,
-        Err(err) => {
-            log::warn!("Unable to create ARP send socket: {err}");
-            return;
-        }
-    };
-    let _ = sock.bind_device(Some(ifname.as_bytes()));
-    let _ = sock.set_broadcast(true);
-
-    let ifindex = match if_index(ifname) {
-        Ok(idx) => idx,
-        Err(err) => {
-            log::warn!("Unable to resolve ifindex for {ifname}: {err}");
-            return;
-        }
-    };
-    let dest = arp_link_addr(ifindex);
-
-    unsafe {
-        match sendto(
-            sock.as_raw_fd(),
-            &arp_frame as *const _ as *const c_void,
-            size_of_val(&arp_frame),
-            0,
-            dest.as_ptr().cast::<sockaddr>(),
-            dest.len(),
-        ) {
-            -1 => {
-                log::warn!("Problem sending ARP message");
-            }
-            _fd => {}
+    let result = with_send_socket(SendKind::Arp, ifname, |sock, ifindex| {
+        let dest = arp_link_addr(ifindex);
+        let sent = unsafe {
+            sendto(
+                sock.as_raw_fd(),
+                &arp_frame as *const _ as *const c_void,
+                size_of_val(&arp_frame),
+                0,
+                dest.as_ptr().cast::<sockaddr>(),
+                dest.len(),
+            )
+        };
+        if sent == -1 {
+            return Err(io::Error::last_os_error());
         }
+        Ok(())
+    });
+    if let Err(err) = result {
+        log::warn!("Problem sending ARP message on {ifname}: {err}");
     }
 }
 
@@ -148,29 +279,23 @@ pub fn send_neighbor_advertisement(
     target_addr: Ipv6Addr,
     na: NdpNeighborAdvertisement,
 ) {
-    let sock = match Socket::new(
-        Domain::IPV6,
-        Type::RAW,
-        Some(Protocol::from(ICMPV6_PROTOCOL_NUMBER)),
-    ) {
-        Ok(sock) => sock,
-        Err(err) => {
-            log::warn!("Unable to create NDP send socket: {err}");
-            return;
-        }
-    };
-    let _ = sock.bind_device(Some(ifname.as_bytes()));
-
     let dst = ALL_NODES_V6_MCAST_ADDR;
     let buf = na.encode(target_addr, dst);
     let saddr = SocketAddrV6::new(dst, 0, 0, 0);
-    if let Err(err) = sock.send_to(&buf, &saddr.into()) {
+    let result = with_send_socket(SendKind::Ndp, ifname, |sock, _| {
+        sock.send_to(&buf, &saddr.into())
+    });
+    if let Err(err) = result {
         log::warn!(
             "Problem sending NDP neighbor advertisement on {ifname}: {err}"
         );
     }
 }
 
+/// A raw VRRP socket bound to `ifname` and joined to the VRRP multicast
+/// group. Shared by every instance on the interface (see `dispatch`), so
+/// it hands back every advert, unicast ones included; filtering by VRID
+/// and unicast peer is left to the caller.
 pub(crate) struct VrrpListener {
     inner: AsyncFd<Socket>,
 }
@@ -200,6 +325,8 @@ impl VrrpListener {
             }
             IpAddr::V6(addr) => {
                 sock.join_multicast_v6(&addr, ifindex)?;
+                set_ipv6_flag(&sock, libc::IPV6_RECVPKTINFO)?;
+                set_ipv6_flag(&sock, libc::IPV6_RECVHOPLIMIT)?;
             }
         }
 
@@ -230,6 +357,97 @@ impl VrrpListener {
             }
         }
     }
+
+    /// IPv6 counterpart of `recv`, which also returns the destination the
+    /// packet was sent to -- the group, or one of our own addresses for a
+    /// unicast advert -- and its hop limit. An IPv6 raw socket strips the
+    /// header, so these come from `IPV6_PKTINFO`/`IPV6_HOPLIMIT` instead;
+    /// the checksum pseudo-header needs the destination.
+    pub(crate) async fn recv_v6(
+        &self,
+    ) -> io::Result<(Vec<u8>, Ipv6Addr, Ipv6Addr, u8)> {
+        loop {
+            let mut guard = self.inner.readable().await?;
+            match guard.try_io(|inner| recv_with_pktinfo_v6(inner.get_ref())) {
+                Ok(result) => return result,
+                Err(_would_block) => continue,
+            }
+        }
+    }
+}
+
+/// Turns on the `IPPROTO_IPV6` boolean socket option `option`.
+fn set_ipv6_flag(sock: &Socket, option: libc::c_int) -> io::Result<()> {
+    let enable: libc::c_int = 1;
+    let ret = unsafe {
+        libc::setsockopt(
+            sock.as_raw_fd(),
+            libc::IPPROTO_IPV6,
+            option,
+            (&enable as *const libc::c_int).cast::<c_void>(),
+            size_of_val(&enable) as libc::socklen_t,
+        )
+    };
+    if ret < 0 {
+        return Err(io::Error::last_os_error());
+    }
+    Ok(())
+}
+
+/// `recvmsg` on a v6 socket with `IPV6_RECVPKTINFO` and
+/// `IPV6_RECVHOPLIMIT` set: the payload, its source, its destination (the
+/// VRRP group if the kernel didn't report one) and its hop limit (0 if the
+/// kernel didn't report one, so it fails the hop limit check).
+fn recv_with_pktinfo_v6(
+    sock: &Socket,
+) -> io::Result<(Vec<u8>, Ipv6Addr, Ipv6Addr, u8)> {
+    let mut buf = [0u8; 512];
+    // u64s so the control buffer is aligned for `cmsghdr`.
+    let mut control = [0u64; 16];
+    let mut src: libc::sockaddr_in6 = unsafe { std::mem::zeroed() };
+    let mut iov = libc::iovec {
+        iov_base: buf.as_mut_ptr().cast::<c_void>(),
+        iov_len: buf.len(),
+    };
+    let mut msg: libc::msghdr = unsafe { std::mem::zeroed() };
+    msg.msg_name = (&mut src as *mut libc::sockaddr_in6).cast::<c_void>();
+    msg.msg_namelen = size_of_val(&src) as libc::socklen_t;
+    msg.msg_iov = &mut iov;
+    msg.msg_iovlen = 1;
+    msg.msg_control = control.as_mut_ptr().cast::<c_void>();
+    msg.msg_controllen = size_of_val(&control);
+
+    let n = unsafe { libc::recvmsg(sock.as_raw_fd(), &mut msg, 0) };
+    if n < 0 {
+        return Err(io::Error::last_os_error());
+    }
+
+    let mut dst = VRRP_V6_MCAST_ADDR;
+    let mut hop_limit = 0;
+    unsafe {
+        let mut cmsg = libc::CMSG_FIRSTHDR(&msg);
+        while !cmsg.is_null() {
+            if (*cmsg).cmsg_level == libc::IPPROTO_IPV6 {
+                let data = libc::CMSG_DATA(cmsg);
+                match (*cmsg).cmsg_type {
+                    libc::IPV6_PKTINFO => {
+                        let info =
+                            data.cast::<libc::in6_pktinfo>().read_unaligned();
+                        dst = Ipv6Addr::from(info.ipi6_addr.s6_addr);
+                    }
+                    libc::IPV6_HOPLIMIT => {
+                        let limit = data.cast::<libc::c_int>().read_unaligned();
+                        hop_limit = u8::try_from(limit).unwrap_or(0);
+                    }
+                    _ => {}
+                }
+            }
+            cmsg = libc::CMSG_NXTHDR(&msg, cmsg);
+        }
+    }
+
+    let src = Ipv6Addr::from(src.sin6_addr.s6_addr);
+    Ok((buf[..n as usize].to_vec(), src, dst, hop_limit))
 }
 
 /// A raw ICMPv6 socket bound to `ifname`, used to send and receive
@@ -342,3 +560,93 @@ impl ArpListener {
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use std::cell::Cell;
+
+    use super::*;
+
+    fn key() -> (String, SendKind) {
+        ("fover4-51-abcd".to_string(), SendKind::Arp)
+    }
+
+    /// Runs `send` through `with_cached_socket`, counting the sockets it
+    /// opens in `opens`.
+    fn send_on(
+        sockets: &mut SendSockets,
+        ifindex: u32,
+        opens: &Cell<u32>,
+        send: impl FnOnce(&Socket, u32) -> io::Result<()>,
+    ) -> io::Result<()> {
+        let open = || {
+            opens.set(opens.get() + 1);
+            Socket::new(Domain::IPV4, Type::DGRAM, None)
+        };
+        with_cached_socket(sockets, key(), ifindex, open, send)
+    }
+
+    #[test]
+    fn socket_is_reused_while_the_interface_stays() {
+        let mut sockets = SendSockets::new();
+        let opens = Cell::new(0);
+
+        send_on(&mut sockets, 7, &opens, |_, _| Ok(())).unwrap();
+        send_on(&mut sockets, 7, &opens, |_, ifindex| {
+            assert_eq!(ifindex, 7);
+            Ok(())
+        })
+        .unwrap();
+
+        assert_eq!(opens.get(), 1);
+    }
+
+    #[test]
+    fn socket_is_reopened_once_the_interface_is_recreated() {
+        let mut sockets = SendSockets::new();
+        let opens = Cell::new(0);
+
+        send_on(&mut sockets, 7, &opens, |_, _| Ok(())).unwrap();
+        send_on(&mut sockets, 9, &opens, |_, _| Ok(())).unwrap();
+
+        assert_eq!(opens.get(), 2);
+        assert_eq!(sockets[&key()].ifindex, 9);
+    }
+
+    #[test]
+    fn socket_is_reopened_after_a_failed_send() {
+        let mut sockets = SendSockets::new();
+        let opens = Cell::new(0);
+
+        let failed = send_on(&mut sockets, 7, &opens, |_, _| {
+            Err(io::Error::from(io::ErrorKind::NetworkDown))
+        });
+        assert!(failed.is_err());
+        assert!(sockets.is_empty());
+
+        send_on(&mut sockets, 7, &opens, |_, _| Ok(())).unwrap();
+        assert_eq!(opens.get(), 2);
+    }
+
+    #[test]
+    fn v6_receive_reports_the_destination_and_hop_limit() {
+        let receiver = Socket::new(Domain::IPV6, Type::DGRAM, None).unwrap();
+        receiver
+            .bind(&SocketAddrV6::new(Ipv6Addr::LOCALHOST, 0, 0, 0).into())
+            .unwrap();
+        set_ipv6_flag(&receiver, libc::IPV6_RECVPKTINFO).unwrap();
+        set_ipv6_flag(&receiver, libc::IPV6_RECVHOPLIMIT).unwrap();
+        let sender = Socket::new(Domain::IPV6, Type::DGRAM, None).unwrap();
+        sender.set_unicast_hops_v6(254).unwrap();
+
+        let to = receiver.local_addr().unwrap();
+        sender.send_to(b"advert", &to).unwrap();
+        let (data, src, dst, hop_limit) =
+            recv_with_pktinfo_v6(&receiver).unwrap();
+
+        assert_eq!(data, b"advert");
+        assert_eq!(src, Ipv6Addr::LOCALHOST);
+        assert_eq!(dst, Ipv6Addr::LOCALHOST);
+        assert_eq!(hop_limit, 254);
+    }
+}
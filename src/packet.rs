@@ -11,6 +11,7 @@ use crate::{VrrpAddresses, VrrpVersion};
 const VRRP_PROTO_NUM: u8 = 112;
 const ICMPV6_PROTO_NUM: u8 = 58;
 
+pub(crate) const VRRP_MCAST_ADDR: Ipv4Addr = Ipv4Addr::new(224, 0, 0, 18);
 pub(crate) const VRRP_V6_MCAST_ADDR: Ipv6Addr =
     Ipv6Addr::new(0xff02, 0, 0, 0, 0, 0, 0, 0x12);
 pub(crate) const ALL_NODES_V6_MCAST_ADDR: Ipv6Addr =
@@ -42,6 +43,65 @@ pub struct VrrpPacket {
     pub priority: u8,
     pub adver_int_cs: u16,
     pub addresses: VrrpAddresses,
+    /// Only carried by v2; always `V2Auth::None` for v3.
+    pub auth: V2Auth,
+}
+
+/// VRRPv2 authentication (RFC 3768 section 5.3.6): the Auth Type field and
+/// the 8-byte auth data trailer. v3 dropped authentication entirely.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Default)]
+pub enum V2Auth {
+    /// Auth Type 0; the trailer is zeroed and ignored on receipt.
+    #[default]
+    None,
+    /// Auth Type 1: a clear-text password of up to 8 bytes, zero-padded.
+    SimpleText([u8; 8]),
+    /// Any other Auth Type seen on the wire (e.g. the retired IP AH, 2).
+    Unsupported(u8),
+}
+
+impl V2Auth {
+    /// Zero-pads `password` into the trailer; anything past 8 bytes is cut
+    /// off (config validation rejects that up front).
+    pub(crate) fn simple_text(password: &str) -> Self {
+        let mut data = [0; 8];
+        let len = password.len().min(data.len());
+        data[..len].copy_from_slice(&password.as_bytes()[..len]);
+        Self::SimpleText(data)
+    }
+
+    pub(crate) fn auth_type(&self) -> u8 {
+        match self {
+            Self::None => 0,
+            Self::SimpleText(_) => 1,
+            Self::Unsupported(auth_type) => *auth_type,
+        }
+    }
+
+    fn data(&self) -> [u8; 8] {
+        match self {
+            Self::SimpleText(data) => *data,
+            Self::None | Self::Unsupported(_) => [0; 8],
+        }
+    }
+
+    fn decode(auth_type: u8, data: [u8; 8]) -> Self {
+        match auth_type {
+            0 => Self::None,
+            1 => Self::SimpleText(data),
+            other => Self::Unsupported(other),
+        }
+    }
+}
+
+impl std::fmt::Display for V2Auth {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::None => write!(f, "none"),
+            Self::SimpleText(_) => write!(f, "simple-text"),
+            Self::Unsupported(auth_type) => write!(f, "type {auth_type}"),
+        }
+    }
 }
 
 impl VrrpPacket {
@@ -53,18 +113,26 @@ impl VrrpPacket {
     const FIXED_HEADER_LEN: usize = 8;
     const V2_AUTH_TRAILER_LEN: usize = 8;
 
-    /// Encodes this packet for the wire. `src_ip` is only used to build the
-    /// IPv6 pseudo-header checksum when `addresses` is `V6`; it's ignored
-    /// for IPv4 packets, which never need a pseudo-header.
+    /// Encodes this packet for the wire, addressed to the VRRP multicast
+    /// group. `src_ip` is only used to build the IPv6 pseudo-header
+    /// checksum when `addresses` is `V6`; it's ignored for IPv4 packets,
+    /// which never need a pseudo-header.
     pub fn encode(&self, src_ip: IpAddr) -> BytesMut {
+        self.encode_to(src_ip, IpAddr::V6(VRRP_V6_MCAST_ADDR))
+    }
+
+    /// [`Self::encode`] for a packet sent to `dst_ip` rather than the
+    /// multicast group (unicast peering), which the IPv6 pseudo-header has
+    /// to cover instead.
+    pub fn encode_to(&self, src_ip: IpAddr, dst_ip: IpAddr) -> BytesMut {
         match &self.addresses {
             VrrpAddresses::V4(addrs) => self.encode_v4(addrs),
             VrrpAddresses::V6(addrs) => {
-                let src = match src_ip {
+                let as_v6 = |ip| match ip {
                     IpAddr::V6(v6) => v6,
                     IpAddr::V4(_) => Ipv6Addr::UNSPECIFIED,
                 };
-                self.encode_v6(src, addrs)
+                self.encode_v6(as_v6(src_ip), as_v6(dst_ip), addrs)
             }
         }
     }
@@ -84,7 +152,7 @@ impl VrrpPacket {
 
         match self.version {
             VrrpVersion::V2 => {
-                buf.put_u8(0); // Auth type.
+                buf.put_u8(self.auth.auth_type());
                 buf.put_u8((self.adver_int_cs / 100) as u8);
             }
             VrrpVersion::V3 => {
@@ -98,8 +166,7 @@ impl VrrpPacket {
         }
 
         if self.version == VrrpVersion::V2 {
-            buf.put_u32(0); // Auth data 1.
-            buf.put_u32(0); // Auth data 2.
+            buf.put_slice(&self.auth.data()); // Auth data 1 and 2.
         }
 
         let mut check = Checksum::new();
@@ -108,7 +175,12 @@ impl VrrpPacket {
         buf
     }
 
-    fn encode_v6(&self, src_ip: Ipv6Addr, addrs: &[Ipv6Addr]) -> BytesMut {
+    fn encode_v6(
+        &self,
+        src_ip: Ipv6Addr,
+        dst_ip: Ipv6Addr,
+        addrs: &[Ipv6Addr],
+    ) -> BytesMut {
         let mut buf =
             BytesMut::with_capacity(Self::FIXED_HEADER_LEN + addrs.len() * 16);
 
@@ -126,7 +198,7 @@ impl VrrpPacket {
 
         let mut check = Checksum::new();
         check.add_bytes(&src_ip.octets());
-        check.add_bytes(&VRRP_V6_MCAST_ADDR.octets());
+        check.add_bytes(&dst_ip.octets());
         check.add_bytes(&(buf.len() as u32).to_be_bytes());
         check.add_bytes(&[0, 0, 0, VRRP_PROTO_NUM]);
         check.add_bytes(&buf);
@@ -168,15 +240,15 @@ impl VrrpPacket {
         let priority = buf.get_u8();
         let count_ip = buf.get_u8();
 
-        let adver_int_cs = match version {
+        let (adver_int_cs, auth_type) = match version {
             VrrpVersion::V2 => {
-                let _auth_type = buf.get_u8();
+                let auth_type = buf.get_u8();
                 let seconds = buf.get_u8();
-                seconds as u16 * 100
+                (seconds as u16 * 100, auth_type)
             }
             VrrpVersion::V3 => {
                 let field = buf.get_u16();
-                field & 0x0FFF
+                (field & 0x0FFF, 0)
             }
         };
 
@@ -204,10 +276,13 @@ impl VrrpPacket {
             addrs.push(Ipv4Addr::from_bits(buf.get_u32()));
         }
 
-        if version == VrrpVersion::V2 {
-            let _auth_data1 = buf.get_u32();
-            let _auth_data2 = buf.get_u32();
-        }
+        let auth = if version == VrrpVersion::V2 {
+            let mut data = [0; 8];
+            buf.copy_to_slice(&mut data);
+            V2Auth::decode(auth_type, data)
+        } else {
+            V2Auth::None
+        };
 
         Ok(Self {
             version,
@@ -215,6 +290,7 @@ impl VrrpPacket {
             priority,
             adver_int_cs,
             addresses: VrrpAddresses::V4(addrs),
+            auth,
         })
     }
 
@@ -269,6 +345,7 @@ impl VrrpPacket {
             priority,
             adver_int_cs,
             addresses: VrrpAddresses::V6(addrs),
+            auth: V2Auth::None,
         })
     }
 
@@ -430,6 +507,7 @@ impl NdpNeighborAdvertisement {
 /// A hand-rolled ICMPv6 Neighbor Solicitation (RFC 4861 section 4.3),
 /// decoded just far enough to answer "who is being asked about" so a
 /// MASTER can reply the way it replies to ARP requests on IPv4.
+#[derive(Clone, Debug)]
 pub struct NdpNeighborSolicitation {
     pub target_address: Ipv6Addr,
 }
@@ -462,6 +540,7 @@ mod tests {
             priority: 100,
             adver_int_cs: 100,
             addresses: VrrpAddresses::V4(addrs),
+            auth: V2Auth::None,
         }
     }
 
@@ -475,6 +554,7 @@ mod tests {
             priority: 100,
             adver_int_cs: 250,
             addresses: VrrpAddresses::V6(addrs),
+            auth: V2Auth::None,
         }
     }
 
@@ -498,6 +578,45 @@ mod tests {
         }
     }
 
+    #[test]
+    fn v2_simple_text_auth_roundtrips_through_the_trailer() {
+        let mut pkt = sample_v4_packet(VrrpVersion::V2, 1);
+        pkt.auth = V2Auth::simple_text("secret");
+        let encoded = pkt.encode(DUMMY_V4);
+
+        assert_eq!(encoded[4], 1);
+        assert_eq!(&encoded[encoded.len() - 8..], b"secret\0\0");
+        let decoded = VrrpPacket::decode(&encoded, DUMMY_V4, DUMMY_V4).unwrap();
+        assert_eq!(decoded.auth, pkt.auth);
+    }
+
+    #[test]
+    fn v2_auth_type_0_ignores_the_trailer() {
+        let mut encoded = sample_v4_packet(VrrpVersion::V2, 1).encode(DUMMY_V4);
+        let len = encoded.len();
+        encoded[len - 8..].copy_from_slice(b"garbage!");
+        // Fix up the checksum over the altered trailer.
+        encoded[6..8].copy_from_slice(&[0, 0]);
+        let mut check = Checksum::new();
+        check.add_bytes(&encoded);
+        encoded[6..8].copy_from_slice(&check.checksum());
+
+        let decoded = VrrpPacket::decode(&encoded, DUMMY_V4, DUMMY_V4).unwrap();
+        assert_eq!(decoded.auth, V2Auth::None);
+    }
+
+    #[test]
+    fn simple_text_password_is_zero_padded_and_capped_at_8_bytes() {
+        assert_eq!(
+            V2Auth::simple_text("abc"),
+            V2Auth::SimpleText(*b"abc\0\0\0\0\0")
+        );
+        assert_eq!(
+            V2Auth::simple_text("0123456789"),
+            V2Auth::SimpleText(*b"01234567")
+        );
+    }
+
     #[test]
     fn v3_v4_encode_decode_roundtrip_has_no_auth_trailer() {
         let pkt = sample_v4_packet(VrrpVersion::V3, 2);
@@ -537,6 +656,27 @@ mod tests {
         ));
     }
 
+    #[test]
+    fn v6_unicast_checksum_covers_the_peer_address() {
+        let src = Ipv6Addr::new(0xfd00, 0, 0, 0, 0, 0, 0, 1);
+        let peer = Ipv6Addr::new(0xfd00, 0, 0, 0, 0, 0, 0, 2);
+        let encoded =
+            sample_v6_packet(1).encode_to(IpAddr::V6(src), IpAddr::V6(peer));
+
+        assert!(
+            VrrpPacket::decode(&encoded, IpAddr::V6(src), IpAddr::V6(peer))
+                .is_ok()
+        );
+        assert!(matches!(
+            VrrpPacket::decode(
+                &encoded,
+                IpAddr::V6(src),
+                IpAddr::V6(VRRP_V6_MCAST_ADDR)
+            ),
+            Err(PacketError::BadChecksum)
+        ));
+    }
+
     #[test]
     fn v3_interval_field_is_clamped_to_12_bits() {
         let mut pkt = sample_v4_packet(VrrpVersion::V3, 1);
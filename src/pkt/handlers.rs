@@ -7,116 +7,127 @@
 ///
 /// The actions on each of the above are specified in section 6 of RFC 3768
 /// (v2) and section 6 of RFC 5798 (v3).
-use std::net::{IpAddr, Ipv6Addr};
-use std::sync::{Arc, Mutex};
+use std::net::{IpAddr, Ipv4Addr};
 
-use pnet::datalink;
-use pnet::packet::Packet;
-use pnet::packet::ethernet::EthernetPacket;
-use pnet::packet::ipv4::Ipv4Packet;
-
-use crate::error::{NetworkError, PacketError};
-use crate::general::{get_interface, virtual_address_action};
-use crate::observer::EventObserver;
+use crate::error::PacketError;
 use crate::packet::{
     ARPframe, ArpPacket, EthernetFrame, NdpNeighborAdvertisement,
-    NdpNeighborSolicitation, VRRP_V6_MCAST_ADDR, VrrpPacket,
+    NdpNeighborSolicitation, VrrpPacket,
 };
 use crate::router::VirtualRouter;
-use crate::state_machine::{Event, State};
-use crate::{AddressAction, NetResult, VrrpAddresses, network};
+use crate::state_machine::{Advert, State};
+use crate::{VrrpAddresses, VrrpVersion, links, network};
+
+const ETHERTYPE_ARP: u16 = 0x0806;
+const ARP_REQUEST: u16 = 1;
+const ARP_REPLY: u16 = 2;
+const BROADCAST_MAC: [u8; 6] = [0xff; 6];
+
+/// Decides how to answer one incoming ARP frame (RFC 3768/5798 section 6),
+/// returning the reply to send, if any. `frame_dst`/`frame_src` are the
+/// Ethernet header's addresses; `virtual_mac` is this router's
+/// `00-00-5E-00-01-{VRID}`.
+///
+/// Only a MASTER ever answers, and only requests for a virtual IP sent to
+/// the broadcast or virtual MAC. A BACKUP (like INIT/FAULT) MUST NOT
+/// answer for the virtual IP(s), so it never replies at all.
+///
+/// Frames a BACKUP gets sent to the virtual MAC are dropped before they
+/// reach here, by the ingress filter the actor installs through
+/// `general::virtual_mac_discard_action`.
+pub(crate) fn arp_reply(
+    state: State,
+    vips: &[Ipv4Addr],
+    virtual_mac: [u8; 6],
+    frame_dst: [u8; 6],
+    frame_src: [u8; 6],
+    arp: &ArpPacket,
+) -> Option<(EthernetFrame, ArpPacket)> {
+    if state != State::Master {
+        return None;
+    }
 
-pub(crate) fn handle_incoming_arp_pkt(
-    eth_packet: &EthernetPacket<'_>,
-    vrouter: Arc<Mutex<VirtualRouter>>,
-) -> NetResult<()> {
-    let vrouter = match vrouter.lock() {
-        Ok(vr) => vr,
-        Err(_) => {
-            log::error!("Unable to create mutex lock for vrouter");
-            return Err(NetworkError::LockPoisoned);
-        }
-    };
-    let interface = get_interface(&vrouter.mac_vlan_interface_v4)?;
-    let arp_packet = match ArpPacket::decode(eth_packet.payload()) {
-        Some(arp_packet) => arp_packet,
-        None => return Ok(()),
-    };
+    // Only Ethernet/IPv4 requests, and never our own frames (e.g. our
+    // gratuitous ARPs seen again), otherwise we end up replying to our own
+    // reply forever.
+    if arp.hw_type != 1
+        || arp.proto_type != 0x0800
+        || arp.operation != ARP_REQUEST
+        || frame_src == virtual_mac
+        || arp.sender_hw_address == virtual_mac
+    {
+        return None;
+    }
 
-    let interface_mac = match interface.clone().mac {
-        Some(mac) => mac,
-        None => {
-            log::warn!(
-                "interface {} does not have mac address. Unable to continue with incoming VRRP packet checks",
-                &interface.name
-            );
-            return Ok(());
-        }
-    };
+    // A frame unicast to some other station's MAC isn't ours to answer.
+    if frame_dst != BROADCAST_MAC && frame_dst != virtual_mac {
+        return None;
+    }
 
-    match vrouter.fsm.state {
-        State::Init => {}
-        State::Backup => {
-            // MUST NOT respond to ARP requests for the IP address(s) associated
-            // with the virtual router.
-            for ip in &vrouter.ipv4_addresses {
-                if ip.addr().octets() == arp_packet.target_proto_address {
-                    return Ok(());
-                }
-            }
+    let target = Ipv4Addr::from(arp.target_proto_address);
+    if !vips.contains(&target) {
+        return None;
+    }
 
-            // !TODO
-            // MUST discard packets with a destination link layer MAC address
-            // equal to the virtual router MAC address.
-            if arp_packet.target_hw_address == interface_mac.octets() {
-                return Ok(());
-            }
-        }
+    // A gratuitous request (sender IP == target IP) announces the sender's
+    // own binding rather than asking for ours.
+    if arp.sender_proto_address == arp.target_proto_address {
+        return None;
+    }
 
-        State::Master => {
-            // Ignore anything that isn't an ARP request, and ignore
-            // packets we sent ourselves (e.g. our own gratuitous ARPs
-            // looped back by the raw socket), otherwise we end up
-            // replying to our own reply forever.
-            if arp_packet.operation != 1
-                || arp_packet.sender_hw_address == interface_mac.octets()
-            {
-                return Ok(());
-            }
+    // MUST respond to ARP requests for the virtual IP(s) with the virtual
+    // MAC. A probe (RFC 5227, sender IP 0.0.0.0) gets the same unicast
+    // reply, addressed back to 0.0.0.0, so the prober sees the address is
+    // in use.
+    let eth = EthernetFrame {
+        dst_mac: arp.sender_hw_address,
+        src_mac: virtual_mac,
+        ethertype: ETHERTYPE_ARP,
+    };
+    let reply = ArpPacket {
+        hw_type: 1,
+        proto_type: 0x0800,
+        hw_length: 6,
+        proto_length: 4,
+        operation: ARP_REPLY,
+        sender_hw_address: virtual_mac,
+        sender_proto_address: arp.target_proto_address,
+        target_hw_address: arp.sender_hw_address,
+        target_proto_address: arp.sender_proto_address,
+    };
+    Some((eth, reply))
+}
 
-            // MUST respond to ARP requests for the IP address(s) associated
-            // with the virtual router.
-            for ip in &vrouter.ipv4_addresses {
-                if ip.addr().octets() == arp_packet.target_proto_address {
-                    let eth_frame = EthernetFrame {
-                        dst_mac: eth_packet.get_source().octets(),
-                        src_mac: interface_mac.octets(),
-                        ethertype: 0x806,
-                    };
-
-                    let arp_packet = ArpPacket {
-                        hw_type: 1,
-                        proto_type: 0x0800,
-                        hw_length: 6,
-                        proto_length: 4,
-                        operation: 2,
-                        sender_hw_address: interface_mac.octets(),
-                        sender_proto_address: arp_packet.target_proto_address,
-                        target_hw_address: arp_packet.sender_hw_address,
-                        target_proto_address: arp_packet.sender_proto_address,
-                    };
-
-                    let arp_frame = ARPframe::new(eth_frame, arp_packet);
-                    network::send_packet_arp(
-                        interface.name.as_str(),
-                        arp_frame,
-                    );
-                }
-            }
-        }
-    }
+/// Handles an ARP request seen on the parent interface, which sees every
+/// frame regardless of which of its mac-vlans it's delivered to; decoded
+/// once by `dispatch` and handed only to the instance whose virtual IP it
+/// asks about. Replies go out over the v4 mac-vlan, i.e. from the virtual
+/// MAC.
+pub(crate) fn handle_incoming_arp_pkt(
+    frame_dst: [u8; 6],
+    frame_src: [u8; 6],
+    arp_packet: &ArpPacket,
+    vrouter: &VirtualRouter,
+) {
+    let interface = &vrouter.mac_vlan_interface_v4;
+    let Some(interface_mac) = links::mac(interface) else {
+        log::warn!(
+            "interface {interface} does not have mac address. Unable to continue with incoming ARP packet checks"
+        );
+        return;
+    };
 
-    Ok(())
+    if let Some((eth_frame, reply)) = arp_reply(
+        vrouter.fsm.state,
+        &vrouter.ipv4_addrs(),
+        interface_mac,
+        frame_dst,
+        frame_src,
+        arp_packet,
+    ) {
+        let arp_frame = ARPframe::new(eth_frame, reply);
+        network::send_packet_arp(interface, arp_frame);
+    }
 }
 
 /// IPv6's equivalent of `handle_incoming_arp_pkt`: only ever called for a
@@ -127,177 +138,123 @@ pub(crate) fn handle_incoming_arp_pkt(
 /// bookkeeping against, so this is intentionally the minimal behaviour
 /// needed for failover to still work: announce ownership when asked.
 pub(crate) fn handle_incoming_ndp_pkt(
-    payload: &[u8],
-    _src_ip: Ipv6Addr,
-    vrouter: Arc<Mutex<VirtualRouter>>,
-) -> NetResult<()> {
-    let vrouter = match vrouter.lock() {
-        Ok(vr) => vr,
-        Err(_) => {
-            log::error!("Unable to create mutex lock for vrouter");
-            return Err(NetworkError::LockPoisoned);
-        }
-    };
-
+    ns: &NdpNeighborSolicitation,
+    vrouter: &VirtualRouter,
+) {
     let Some(v6_iface) = vrouter.mac_vlan_interface_v6.clone() else {
-        return Ok(());
+        return;
     };
 
     if vrouter.fsm.state != State::Master {
-        return Ok(());
+        return;
     }
 
-    let Some(ns) = NdpNeighborSolicitation::decode(payload) else {
-        return Ok(());
-    };
-
     if !vrouter
         .ipv6_addresses
         .iter()
         .any(|ip| ip.addr() == ns.target_address)
     {
-        return Ok(());
+        return;
     }
 
-    let interface = get_interface(&v6_iface)?;
-    let Some(interface_mac) = interface.mac else {
-        return Ok(());
+    let Some(interface_mac) = links::mac(&v6_iface) else {
+        return;
     };
 
     let na = NdpNeighborAdvertisement {
         target_address: ns.target_address,
-        target_link_addr: interface_mac.octets(),
+        target_link_addr: interface_mac,
         override_flag: true,
     };
     network::send_neighbor_advertisement(&v6_iface, ns.target_address, na);
-
-    Ok(())
 }
 
-/// Logs why an incoming VRRP packet is being dropped
-fn log_drop(vrouter_name: &str, reason: PacketError) {
+/// Logs why an incoming VRRP packet is being dropped. `name` is the
+/// instance's, or the interface's for a packet that never reached one.
+pub(crate) fn log_drop(name: &str, reason: PacketError) {
     match reason {
-        PacketError::VridMismatch { .. } => {
-            log::trace!("({vrouter_name}) dropping VRRP packet: {reason}");
+        PacketError::UnknownVrid(_) => {
+            log::trace!("({name}) dropping VRRP packet: {reason}");
         }
-        PacketError::BadTtl(_) | PacketError::BadChecksum => {
-            log::warn!("({vrouter_name}) dropping VRRP packet: {reason}");
+        PacketError::BadTtl(_)
+        | PacketError::BadChecksum
+        | PacketError::LocalAddressOwner
+        | PacketError::AuthMismatch { .. } => {
+            log::warn!("({name}) dropping VRRP packet: {reason}");
         }
         _ => {
-            log::error!("({vrouter_name}) dropping VRRP packet: {reason}");
+            log::error!("({name}) dropping VRRP packet: {reason}");
         }
     }
 }
 
-pub(crate) fn handle_incoming_vrrp_v4_pkt(
-    ip_packet: &Ipv4Packet<'_>,
-    vrouter_mutex: Arc<Mutex<VirtualRouter>>,
-) -> NetResult<()> {
-    for interface in datalink::interfaces().iter() {
-        if interface
-            .ips
-            .iter()
-            .any(|ip| ip.ip() == ip_packet.get_source())
-        {
-            return Ok(());
-        }
-    }
-
-    process_vrrp_packet(
-        ip_packet.payload(),
-        IpAddr::V4(ip_packet.get_source()),
-        IpAddr::V4(ip_packet.get_destination()),
-        ip_packet.get_ttl(),
-        vrouter_mutex,
-    )
-}
-
-/// IPv6 counterpart of `handle_incoming_vrrp_v4_pkt`. `payload` is already
-/// just the VRRP message (an IPv6 raw socket doesn't hand us the IP
-/// header the way an IPv4 one does), so there's no header to strip and no
-/// hop-limit to check here -- see `network::VrrpListenerV6` for why.
-pub(crate) fn handle_incoming_vrrp_v6_pkt(
-    payload: &[u8],
-    src_ip: Ipv6Addr,
-    vrouter_mutex: Arc<Mutex<VirtualRouter>>,
-) -> NetResult<()> {
-    for interface in datalink::interfaces().iter() {
-        if interface.ips.iter().any(|ip| ip.ip() == IpAddr::V6(src_ip)) {
-            return Ok(());
-        }
-    }
-
-    process_vrrp_packet(
-        payload,
-        IpAddr::V6(src_ip),
-        IpAddr::V6(VRRP_V6_MCAST_ADDR),
-        255,
-        vrouter_mutex,
-    )
-}
-
-fn process_vrrp_packet(
-    payload: &[u8],
+/// Runs the receive checks on one instance's side of an advert received on
+/// a shared listener, returning what the state machine needs of it if it
+/// passes: `dispatch` has already decoded it (checksum included) and
+/// matched its VRID -- and, with unicast peers, its source -- to this
+/// instance. `ttl` is the IP TTL/hop-limit it arrived with.
+pub(crate) fn accept_advert(
+    vrrp_packet: &VrrpPacket,
     src_ip: IpAddr,
-    dst_ip: IpAddr,
     ttl: u8,
-    vrouter_mutex: Arc<Mutex<VirtualRouter>>,
-) -> NetResult<()> {
-    let mut vrouter = match vrouter_mutex.lock() {
-        Ok(vr) => vr,
-        Err(err) => {
-            log::warn!("problem fetching vrouter mutex");
-            log::warn!("{err}");
-            return Ok(());
-        }
-    };
-
-    let vrrp_packet = match VrrpPacket::decode(payload, src_ip, dst_ip) {
-        Ok(pkt) => pkt,
-        Err(err) => {
-            log_drop(&vrouter.name, err);
-            return Ok(());
-        }
-    };
-
+    vrouter: &VirtualRouter,
+) -> Option<Advert> {
     // MUST DO verifications(rfc3768 section 7.1 / rfc5798 section 5.2.x).
     {
         // 1. Verify IP TTL/hop-limit is 255.
         if ttl != 255 {
             log_drop(&vrouter.name, PacketError::BadTtl(ttl));
-            return Ok(());
+            return None;
         }
 
-        // The VRRP checksum is now verified inside `VrrpPacket::decode`
-        // itself (it needs the IPv6 pseudo-header for a v6 packet, which
-        // only the caller has), so a bad checksum already surfaced as a
-        // decode error above.
+        // The VRRP checksum is verified inside `VrrpPacket::decode` itself
+        // (it needs the IPv6 pseudo-header for a v6 packet), so a bad
+        // checksum never gets dispatched here.
 
         // 5. MUST verify that the VRID is configured on the receiving
-        //      interface and the local router is not the IP Address owner.
-        if vrrp_packet.vrid != vrouter.vrid {
+        //      interface (done by `dispatch`) and the local router is not
+        //      the IP Address owner.
+        // 4. (v2 only) MUST perform authentication specified by Auth Type.
+        //      Checked after the VRID so other VRs' passwords on the same
+        //      segment aren't reported. v3 packets always decode with no
+        //      auth.
+        if vrrp_packet.auth != vrouter.auth {
             log_drop(
                 &vrouter.name,
-                PacketError::VridMismatch {
-                    expected: vrouter.vrid,
-                    received: vrrp_packet.vrid,
+                PacketError::AuthMismatch {
+                    expected: vrouter.auth.to_string(),
+                    received: vrrp_packet.auth.to_string(),
                 },
             );
-            return Ok(());
+            return None;
+        }
+        // An owner held in BACKUP by `initial_state` still listens, so it
+        // can choose (via preempt_mode/preempt_delay) when to take back over.
+        if vrouter.is_address_owner() && vrouter.fsm.state == State::Master {
+            log_drop(&vrouter.name, PacketError::LocalAddressOwner);
+            return None;
         }
 
-        // 7. MUST verify that the Adver Interval in the packet is the same as
-        //      the locally configured for this virtual router.
-        let expected_adver_int_cs = vrouter.advert_interval as u16 * 100;
-        if vrrp_packet.adver_int_cs != expected_adver_int_cs {
+        // 7. (v2 only) MUST verify that the Adver Interval in the packet is
+        //      the same as the locally configured for this virtual router.
+        //      A v3 BACKUP instead adopts whatever the MASTER advertises
+        //      (RFC 5798 section 6.4.2), so only a zero interval -- which
+        //      can never match -- is dropped there.
+        let interval_mismatch = match vrouter.version {
+            VrrpVersion::V2 => {
+                vrrp_packet.adver_int_cs != vrouter.advert_interval_cs
+            }
+            VrrpVersion::V3 => vrrp_packet.adver_int_cs == 0,
+        };
+        if interval_mismatch {
             log_drop(
                 &vrouter.name,
                 PacketError::AdvertIntervalMismatch {
-                    expected: vrouter.advert_interval,
-                    received: (vrrp_packet.adver_int_cs / 100) as u8,
+                    expected: vrouter.advert_interval_cs,
+                    received: vrrp_packet.adver_int_cs,
                 },
             );
-            return Ok(());
+            return None;
         }
     }
 
@@ -332,95 +289,176 @@ fn process_vrrp_packet(
             },
         );
         if vrrp_packet.priority != 255 {
-            return Ok(());
+            return None;
         }
     }
 
     if !addr_check && vrrp_packet.priority != 255 {
         log_drop(&vrouter.name, PacketError::IpListMismatch);
-        return Ok(());
+        return None;
     }
 
-    let (mac_vlan_iface, str_addresses) = match &vrrp_packet.addresses {
-        VrrpAddresses::V4(_) => (
-            vrouter.mac_vlan_interface_v4.clone(),
-            vrouter.str_ipv4_addresses(),
-        ),
-        VrrpAddresses::V6(_) => match &vrouter.mac_vlan_interface_v6 {
-            Some(iface) => (iface.clone(), vrouter.str_ipv6_addresses()),
-            None => return Ok(()),
-        },
-    };
+    // A v2 instance has no v6 side to take an IPv6 advert for.
+    if let VrrpAddresses::V6(_) = vrrp_packet.addresses
+        && vrouter.mac_vlan_interface_v6.is_none()
+    {
+        return None;
+    }
 
-    match vrouter.fsm.state {
-        State::Backup => {
-            if vrrp_packet.priority == 0 {
-                let skew_time = vrouter.skew_time;
-                vrouter.fsm.set_master_down_timer(skew_time);
-            } else if !vrouter.preempt_mode
-                || vrrp_packet.priority >= vrouter.priority
-            {
-                let m_down_interval = vrouter.master_down_interval;
-                vrouter.fsm.set_master_down_timer(m_down_interval);
-            } else if vrouter.priority > vrrp_packet.priority {
-                virtual_address_action(
-                    AddressAction::Add,
-                    &str_addresses,
-                    &mac_vlan_iface,
-                );
-                vrouter.fsm.state = State::Master;
-                let advert_interval = vrouter.advert_interval as f32;
-                vrouter.fsm.set_advert_timer(advert_interval);
-                log::info!("({}) transitioned to MASTER", vrouter.name);
-            }
-            Ok(())
+    Some(Advert {
+        priority: vrrp_packet.priority,
+        adver_int_cs: vrrp_packet.adver_int_cs,
+        src: src_ip,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::router::tests::sample;
+
+    const VIRTUAL_MAC: [u8; 6] = [0x00, 0x00, 0x5e, 0x00, 0x01, 51];
+    const HOST_MAC: [u8; 6] = [0x02, 0, 0, 0, 0, 0x0a];
+    const OTHER_MAC: [u8; 6] = [0x02, 0, 0, 0, 0, 0x0b];
+    const VIP: Ipv4Addr = Ipv4Addr::new(192, 168, 100, 10);
+    const HOST_IP: Ipv4Addr = Ipv4Addr::new(192, 168, 100, 20);
+
+    fn request(sender_ip: Ipv4Addr, target_ip: Ipv4Addr) -> ArpPacket {
+        ArpPacket {
+            hw_type: 1,
+            proto_type: 0x0800,
+            hw_length: 6,
+            proto_length: 4,
+            operation: ARP_REQUEST,
+            sender_hw_address: HOST_MAC,
+            sender_proto_address: sender_ip.octets(),
+            target_hw_address: [0; 6],
+            target_proto_address: target_ip.octets(),
         }
+    }
 
-        State::Master => {
-            let adv_priority_gt_local_priority =
-                vrrp_packet.priority > vrouter.priority;
-            let adv_priority_eq_local_priority =
-                vrrp_packet.priority == vrouter.priority;
-
-            // If an ADVERTISEMENT is received, then
-            if vrrp_packet.priority == 0 {
-                // send ADVERTISEMENT
-                vrouter.send_advertisement();
-                let advert_interval = vrouter.advert_interval as f32;
-                vrouter.fsm.set_advert_timer(advert_interval);
-
-                Ok(())
-            } else if adv_priority_gt_local_priority {
-                // delete virtual IP address
-                virtual_address_action(
-                    AddressAction::Delete,
-                    &str_addresses,
-                    &mac_vlan_iface,
-                );
-                let m_down_interval = vrouter.master_down_interval;
-                vrouter.fsm.set_master_down_timer(m_down_interval);
-                vrouter.fsm.state = State::Backup;
-                log::info!("({}) transitioned to BACKUP", vrouter.name);
-                EventObserver::notify_mut(vrouter, Event::Null)?;
-                Ok(())
-            } else if adv_priority_eq_local_priority {
-                // delete virtual IP address
-                virtual_address_action(
-                    AddressAction::Delete,
-                    &str_addresses,
-                    &mac_vlan_iface,
-                );
-                let m_down_interval = vrouter.master_down_interval;
-                vrouter.fsm.set_master_down_timer(m_down_interval);
-                vrouter.fsm.state = State::Backup;
-                vrouter.fsm.event = Event::Null;
-                log::info!("({}) transitioned to BACKUP", vrouter.name);
-                EventObserver::notify_mut(vrouter, Event::Null)?;
-                Ok(())
-            } else {
-                Ok(())
-            }
+    fn reply_to(
+        state: State,
+        frame_dst: [u8; 6],
+        arp: &ArpPacket,
+    ) -> Option<(EthernetFrame, ArpPacket)> {
+        arp_reply(state, &[VIP], VIRTUAL_MAC, frame_dst, HOST_MAC, arp)
+    }
+
+    #[test]
+    fn master_answers_broadcast_request_with_the_virtual_mac() {
+        let (eth, reply) =
+            reply_to(State::Master, BROADCAST_MAC, &request(HOST_IP, VIP))
+                .unwrap();
+
+        assert_eq!(eth.dst_mac, HOST_MAC);
+        assert_eq!(eth.src_mac, VIRTUAL_MAC);
+        assert_eq!(eth.ethertype, ETHERTYPE_ARP);
+        assert_eq!(reply.operation, ARP_REPLY);
+        assert_eq!(reply.sender_hw_address, VIRTUAL_MAC);
+        assert_eq!(reply.sender_proto_address, VIP.octets());
+        assert_eq!(reply.target_hw_address, HOST_MAC);
+        assert_eq!(reply.target_proto_address, HOST_IP.octets());
+    }
+
+    #[test]
+    fn master_answers_unicast_refresh_sent_to_the_virtual_mac() {
+        assert!(
+            reply_to(State::Master, VIRTUAL_MAC, &request(HOST_IP, VIP))
+                .is_some()
+        );
+    }
+
+    #[test]
+    fn master_ignores_frames_unicast_to_another_station() {
+        assert!(
+            reply_to(State::Master, OTHER_MAC, &request(HOST_IP, VIP))
+                .is_none()
+        );
+    }
+
+    #[test]
+    fn master_ignores_requests_for_other_addresses() {
+        assert!(
+            reply_to(State::Master, BROADCAST_MAC, &request(HOST_IP, HOST_IP))
+                .is_none()
+        );
+        let other = Ipv4Addr::new(192, 168, 100, 99);
+        assert!(
+            reply_to(State::Master, BROADCAST_MAC, &request(HOST_IP, other))
+                .is_none()
+        );
+    }
+
+    #[test]
+    fn master_answers_probes_back_to_the_unspecified_address() {
+        let probe = request(Ipv4Addr::UNSPECIFIED, VIP);
+        let (eth, reply) =
+            reply_to(State::Master, BROADCAST_MAC, &probe).unwrap();
+
+        assert_eq!(eth.dst_mac, HOST_MAC);
+        assert_eq!(reply.target_hw_address, HOST_MAC);
+        assert_eq!(reply.target_proto_address, [0; 4]);
+    }
+
+    #[test]
+    fn master_ignores_gratuitous_requests_and_replies() {
+        assert!(
+            reply_to(State::Master, BROADCAST_MAC, &request(VIP, VIP))
+                .is_none()
+        );
+
+        let mut reply = request(HOST_IP, VIP);
+        reply.operation = ARP_REPLY;
+        assert!(reply_to(State::Master, BROADCAST_MAC, &reply).is_none());
+    }
+
+    #[test]
+    fn master_ignores_its_own_frames() {
+        let mut own = request(VIP, HOST_IP);
+        own.sender_hw_address = VIRTUAL_MAC;
+        own.target_proto_address = VIP.octets();
+        assert!(reply_to(State::Master, BROADCAST_MAC, &own).is_none());
+
+        let looped = arp_reply(
+            State::Master,
+            &[VIP],
+            VIRTUAL_MAC,
+            BROADCAST_MAC,
+            VIRTUAL_MAC,
+            &request(HOST_IP, VIP),
+        );
+        assert!(looped.is_none());
+    }
+
+    #[test]
+    fn backup_never_answers_for_the_virtual_addresses() {
+        for frame_dst in [BROADCAST_MAC, VIRTUAL_MAC] {
+            assert!(
+                reply_to(State::Backup, frame_dst, &request(HOST_IP, VIP))
+                    .is_none()
+            );
         }
-        _ => Ok(()),
+    }
+
+    #[test]
+    fn init_and_fault_never_answer() {
+        for state in [State::Init, State::Fault] {
+            assert!(
+                reply_to(state, BROADCAST_MAC, &request(HOST_IP, VIP))
+                    .is_none()
+            );
+        }
+    }
+
+    #[test]
+    fn adverts_with_a_hop_limit_below_255_are_dropped() {
+        let mut vr = sample(VrrpVersion::V3, 100);
+        vr.fsm.state = State::Backup;
+        let packet = vr.advert_v4(200);
+        let src: IpAddr = "fe80::9".parse().unwrap();
+
+        assert!(accept_advert(&packet, src, 254, &vr).is_none());
+        assert!(accept_advert(&packet, src, 255, &vr).is_some());
     }
 }
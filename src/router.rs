@@ -1,11 +1,20 @@
-use std::net::{Ipv4Addr, Ipv6Addr};
+use std::net::{IpAddr, Ipv4Addr, Ipv6Addr};
+use std::sync::Arc;
 
 use ipnet::{Ipv4Net, Ipv6Net};
+use tokio::sync::Notify;
 
+use crate::announce::GarpSettings;
+use crate::config::{
+    InitialState, LinkBackend, TrackInterface, TrackRoute, TrackScript,
+};
 use crate::packet::{
-    ARPframe, ArpPacket, EthernetFrame, NdpNeighborAdvertisement, VrrpPacket,
+    ARPframe, ArpPacket, EthernetFrame, NdpNeighborAdvertisement, V2Auth,
+    VRRP_MCAST_ADDR, VRRP_V6_MCAST_ADDR, VrrpPacket,
 };
 use crate::state_machine::VirtualRouterMachine;
+use crate::sync::SyncGroupHandle;
+use crate::track::{self, TrackedObjects};
 use crate::{VrrpAddresses, VrrpVersion, network};
 
 #[derive(Debug, Clone)]
@@ -17,15 +26,67 @@ pub struct VirtualRouter {
     pub(crate) ipv6_addresses: Vec<Ipv6Net>,
     pub(crate) priority: u8,
     pub(crate) skew_time: f32,
-    pub(crate) advert_interval: u8,
+    /// Advertisement interval in centiseconds -- the v3 wire unit. Always a
+    /// whole number of seconds (a multiple of 100) for a v2 instance.
+    pub(crate) advert_interval_cs: u16,
+    /// RFC 5798 Master_Adver_Interval: the interval the current MASTER is
+    /// advertising at, learned by a v3 BACKUP from its adverts. Equal to
+    /// `advert_interval_cs` until one is learned, and always for v2. Drives
+    /// `skew_time`/`master_down_interval` for v3.
+    pub(crate) master_adver_interval_cs: u16,
     pub(crate) master_down_interval: f32,
     pub(crate) preempt_mode: bool,
+    /// Seconds a higher-priority BACKUP holds off before preempting; see
+    /// `VirtualRouterMachine::preempt_timer`.
+    pub(crate) preempt_delay: u16,
+    /// RFC 5798 Accept_Mode: whether a non-owner MASTER accepts traffic
+    /// addressed to the virtual IP(s). See `filters_vip_traffic`.
+    pub(crate) accept_mode: bool,
+    /// Seconds the instance holds in INIT, once told to start, before the
+    /// startup transition.
+    pub(crate) startup_delay: u16,
+    pub(crate) initial_state: InitialState,
+    pub(crate) track_interfaces: Vec<TrackInterface>,
+    pub(crate) track_scripts: Vec<TrackScript>,
+    pub(crate) track_routes: Vec<TrackRoute>,
+    /// Health of everything in `track_interfaces`/`track_scripts`/
+    /// `track_routes`; feeds `effective_priority` and FAULT.
+    pub(crate) tracked: TrackedObjects,
+    /// The sync group this instance follows and reports its transitions
+    /// to, if any; see `Action::Publish`.
+    pub(crate) sync_group: Option<SyncGroupHandle>,
+    /// Unicast peering: adverts go to each of these instead of the VRRP
+    /// multicast group. Empty for plain multicast.
+    pub(crate) unicast_peers: Vec<IpAddr>,
+    pub(crate) unicast_src_ip: Option<IpAddr>,
+    /// Sent in, and required of, every v2 advert. Always `None` for v3.
+    pub(crate) auth: V2Auth,
     pub(crate) network_interface: String,
+    /// When false no mac-vlans are created: the virtual IP(s) go straight
+    /// on `network_interface` and everything is sent from its own MAC, with
+    /// `mac_vlan_interface_v4`/`_v6` naming it as well.
+    pub(crate) use_virtual_mac: bool,
+    pub(crate) link_backend: LinkBackend,
     pub(crate) mac_vlan_interface_v4: String,
     /// `Some` only for a v3 instance (v2 never creates a v6 mac-vlan).
     pub(crate) mac_vlan_interface_v6: Option<String>,
     pub(crate) primary_ip: Ipv4Addr,
     pub(crate) primary_ip_v6: Option<Ipv6Addr>,
+    /// Set by `lib::run` when every virtual IP is already a real address on
+    /// `network_interface`; see `set_address_owner`.
+    pub(crate) address_owner: bool,
+    /// Without a virtual MAC, the virtual IP(s) that were already on
+    /// `network_interface` at startup: the host's own, so never added,
+    /// deleted or filtered by this instance.
+    pub(crate) host_addresses: Vec<IpAddr>,
+    /// Set by `Event::Release`: holds off preempting the new MASTER until
+    /// this instance next becomes MASTER of its own accord (e.g. because
+    /// nobody else took over).
+    pub(crate) released: bool,
+    pub(crate) garp: GarpSettings,
+    /// Signalled on every transition to MASTER to (re)start announcing the
+    /// virtual IP(s); see `core_tasks::announce_process`.
+    pub(crate) announce: Arc<Notify>,
     pub(crate) fsm: VirtualRouterMachine,
 }
 
@@ -34,16 +95,127 @@ impl VirtualRouter {
         self.ipv4_addresses.iter().map(|a| a.addr()).collect()
     }
 
+    /// The virtual IPv4 addresses this instance adds and deletes, i.e. all
+    /// but its `host_addresses`.
     pub(crate) fn str_ipv4_addresses(&self) -> Vec<String> {
-        self.ipv4_addresses.iter().map(|a| a.to_string()).collect()
+        self.ipv4_addresses
+            .iter()
+            .filter(|a| !self.is_host_address(IpAddr::V4(a.addr())))
+            .map(|a| a.to_string())
+            .collect()
     }
 
     pub(crate) fn ipv6_addrs(&self) -> Vec<Ipv6Addr> {
         self.ipv6_addresses.iter().map(|a| a.addr()).collect()
     }
 
+    /// `str_ipv4_addresses` for the virtual IPv6 addresses.
     pub(crate) fn str_ipv6_addresses(&self) -> Vec<String> {
-        self.ipv6_addresses.iter().map(|a| a.to_string()).collect()
+        self.ipv6_addresses
+            .iter()
+            .filter(|a| !self.is_host_address(IpAddr::V6(a.addr())))
+            .map(|a| a.to_string())
+            .collect()
+    }
+
+    /// Whether `addr` is one of `host_addresses`.
+    pub(crate) fn is_host_address(&self, addr: IpAddr) -> bool {
+        self.host_addresses.contains(&addr)
+    }
+
+    /// `advert_interval_cs` in (fractional) seconds, the unit the timers in
+    /// `VirtualRouterMachine` are armed with.
+    pub(crate) fn advert_interval_secs(&self) -> f32 {
+        self.advert_interval_cs as f32 / 100_f32
+    }
+
+    /// Whether this router owns the virtual IP(s) (RFC 3768/5798), i.e.
+    /// they're real addresses on its parent interface. An owner always runs
+    /// at priority 255 and never gives up mastership.
+    pub(crate) fn is_address_owner(&self) -> bool {
+        self.address_owner
+    }
+
+    /// Whether, as MASTER, locally-destined traffic to the virtual IP(s) has
+    /// to be dropped: accept_mode is off and we aren't the address owner
+    /// (the owner always accepts, RFC 5798 section 6.1).
+    pub(crate) fn filters_vip_traffic(&self) -> bool {
+        !self.accept_mode && !self.is_address_owner()
+    }
+
+    /// The priority actually advertised and compared against: the address
+    /// owner's 255, otherwise the configured priority lowered by the weight
+    /// of every failing tracked object.
+    pub(crate) fn effective_priority(&self) -> u8 {
+        if self.is_address_owner() {
+            return 255;
+        }
+        track::effective_priority(self.priority, self.tracked.penalty())
+    }
+
+    /// The instance as it stands, for the log line `lib::run` writes on
+    /// SIGUSR2. Shows the Accept_Mode in effect, which is always on for the
+    /// address owner.
+    pub(crate) fn status(&self) -> String {
+        let owner = if self.is_address_owner() {
+            ", address owner"
+        } else {
+            ""
+        };
+        format!(
+            "{:?}, priority {} (configured {}), accept_mode: {}{owner}",
+            self.fsm.state,
+            self.effective_priority(),
+            self.priority,
+            !self.filters_vip_traffic(),
+        )
+    }
+
+    /// Records a tracked object's health (see `TrackedObjects::set`),
+    /// recomputing the priority-dependent intervals if it changed anything.
+    /// Returns whether it did.
+    pub(crate) fn update_tracked(
+        &mut self,
+        key: &str,
+        weight: Option<u8>,
+        healthy: bool,
+    ) -> bool {
+        if !self.tracked.set(key, weight, healthy) {
+            return false;
+        }
+        log::info!(
+            "({}) {key} is {}; effective priority now {}{}",
+            self.name,
+            if healthy { "healthy" } else { "failing" },
+            self.effective_priority(),
+            if self.tracked.is_faulted() {
+                " (FAULT)"
+            } else {
+                ""
+            }
+        );
+        self.recompute_intervals();
+        true
+    }
+
+    /// Whether the startup transition out of INIT goes to MASTER (rather
+    /// than BACKUP), per `initial_state`.
+    pub(crate) fn starts_as_master(&self) -> bool {
+        match self.initial_state {
+            InitialState::Auto => self.is_address_owner(),
+            InitialState::Backup => false,
+            InitialState::Master => true,
+        }
+    }
+
+    /// Whether, as the address owner held in BACKUP by `initial_state`, it
+    /// would still take over on the first advert it hears: preempting,
+    /// with no `preempt_delay` to hold it off.
+    pub(crate) fn owner_preempts_at_once(&self) -> bool {
+        self.is_address_owner()
+            && self.initial_state == InitialState::Backup
+            && self.preempt_mode
+            && self.preempt_delay == 0
     }
 
     pub(crate) fn new(params: VirtualRouterParams) -> Self {
@@ -54,25 +226,27 @@ impl VirtualRouter {
             ipv4_addresses,
             ipv6_addresses,
             priority,
-            advert_interval,
+            advert_interval_cs,
             preempt_mode,
+            preempt_delay,
+            accept_mode,
+            startup_delay,
+            initial_state,
+            track_interfaces,
+            track_scripts,
+            track_routes,
+            sync_group,
+            unicast_peers,
+            unicast_src_ip,
+            auth,
+            garp,
             network_interface,
+            use_virtual_mac,
+            link_backend,
         } = params;
 
-        let skew_time: f32 = match version {
-            // v2 (RFC 3768 section 6.2): a flat sub-second tiebreaker,
-            // independent of the advertisement interval.
-            VrrpVersion::V2 => (256_f32 - priority as f32) / 256_f32,
-            // v3 (RFC 5798 section 6.1): scales with the advertisement
-            // interval now that the interval itself has sub-second
-            // resolution. This is a real formula change, not just a unit
-            // relabeling -- do not collapse this back into the v2 arm.
-            VrrpVersion::V3 => {
-                ((256_f32 - priority as f32) * advert_interval as f32) / 256_f32
-            }
-        };
-        let master_down_interval: f32 =
-            (3_f32 * advert_interval as f32) + skew_time;
+        let (skew_time, master_down_interval) =
+            timer_intervals(version, priority, advert_interval_cs);
 
         // Always create a mac-vlan interface reference for v3 (even before
         // it's actually built), so that "does this instance have a v6
@@ -90,95 +264,267 @@ impl VirtualRouter {
             ipv6_addresses,
             priority,
             skew_time,
-            advert_interval,
+            advert_interval_cs,
+            master_adver_interval_cs: advert_interval_cs,
             master_down_interval,
             preempt_mode,
+            preempt_delay,
+            accept_mode,
+            startup_delay,
+            initial_state,
+            track_interfaces,
+            track_scripts,
+            track_routes,
+            tracked: TrackedObjects::default(),
+            sync_group,
+            unicast_peers,
+            unicast_src_ip,
+            auth,
             network_interface,
+            use_virtual_mac,
+            link_backend,
             mac_vlan_interface_v4: String::new(),
             mac_vlan_interface_v6,
             primary_ip: Ipv4Addr::UNSPECIFIED,
             primary_ip_v6: None,
+            address_owner: false,
+            host_addresses: Vec::new(),
+            released: false,
+            garp,
+            announce: Arc::new(Notify::new()),
             fsm: VirtualRouterMachine::default(),
         }
     }
 
-    /// Builds, checksums and sends VRRP advertisement(s) for this router's
-    /// current vrid/priority/addresses. Always sends an IPv4 advertisement
-    /// over `mac_vlan_interface_v4`; a v3 instance additionally sends an
-    /// IPv6 advertisement over `mac_vlan_interface_v6`.
-    pub(crate) fn send_advertisement(&self) {
-        let adver_int_cs = self.advert_interval as u16 * 100;
+    /// Marks this router as the owner of its virtual IP(s), which runs it
+    /// at priority 255 (and with the matching skew/master-down intervals)
+    /// regardless of the configured priority.
+    pub(crate) fn set_address_owner(&mut self) {
+        self.address_owner = true;
+        self.priority = 255;
+        self.recompute_intervals();
+    }
 
-        let v4_pkt = VrrpPacket {
+    /// RFC 5798 section 6.4.2: a v3 BACKUP adopts the MASTER's advertised
+    /// interval as its Master_Adver_Interval and recomputes
+    /// Master_Down_Interval from it. v2 has no such notion (intervals must
+    /// match exactly), so this is a no-op there.
+    pub(crate) fn learn_master_adver_interval(&mut self, adver_int_cs: u16) {
+        if self.version != VrrpVersion::V3
+            || adver_int_cs == self.master_adver_interval_cs
+        {
+            return;
+        }
+        log::info!(
+            "({}) adopting MASTER's advertisement interval {adver_int_cs}cs (was {}cs)",
+            self.name,
+            self.master_adver_interval_cs
+        );
+        self.master_adver_interval_cs = adver_int_cs;
+        self.recompute_intervals();
+    }
+
+    fn recompute_intervals(&mut self) {
+        (self.skew_time, self.master_down_interval) = timer_intervals(
+            self.version,
+            self.effective_priority(),
+            self.master_adver_interval_cs,
+        );
+    }
+
+    /// Whether adverts are exchanged by unicast with `unicast_peers`
+    /// rather than over the VRRP multicast group.
+    pub(crate) fn is_unicast(&self) -> bool {
+        !self.unicast_peers.is_empty()
+    }
+
+    /// Source address of IPv4 adverts: `unicast_src_ip` when it's IPv4 and
+    /// peering by unicast, the interface's primary address otherwise.
+    pub(crate) fn advert_src_v4(&self) -> Ipv4Addr {
+        match self.unicast_src_ip {
+            Some(IpAddr::V4(src)) if self.is_unicast() => src,
+            _ => self.primary_ip,
+        }
+    }
+
+    /// IPv6 counterpart of `advert_src_v4`; `None` if there's no IPv6
+    /// address to send from.
+    pub(crate) fn advert_src_v6(&self) -> Option<Ipv6Addr> {
+        match self.unicast_src_ip {
+            Some(IpAddr::V6(src)) if self.is_unicast() => Some(src),
+            _ => self.primary_ip_v6,
+        }
+    }
+
+    /// Whether an advert carrying `priority` and sent from `src` outranks
+    /// this router: a higher priority, or an equal one from a higher
+    /// primary address (RFC 3768/5798 section 6.4.3). `src` is compared
+    /// with the address our own adverts of that family are sent from.
+    pub(crate) fn is_outranked_by(&self, priority: u8, src: IpAddr) -> bool {
+        let local_priority = self.effective_priority();
+        if priority != local_priority {
+            return priority > local_priority;
+        }
+        match src {
+            IpAddr::V4(src) => src > self.advert_src_v4(),
+            // Nothing to tell apart without a v6 address of our own.
+            IpAddr::V6(src) => self.advert_src_v6().is_none_or(|own| src > own),
+        }
+    }
+
+    /// The IPv4 advert this router sends carrying `priority`.
+    pub(crate) fn advert_v4(&self, priority: u8) -> VrrpPacket {
+        VrrpPacket {
             version: self.version,
             vrid: self.vrid,
-            priority: self.priority,
-            adver_int_cs,
+            priority,
+            adver_int_cs: self.advert_interval_cs,
             addresses: VrrpAddresses::V4(self.ipv4_addrs()),
-        };
-        let _ = network::send_vrrp_packet_v4(
-            &self.mac_vlan_interface_v4,
-            self.primary_ip,
-            v4_pkt,
-        );
+            auth: self.auth,
+        }
+    }
+
+    /// Builds, checksums and sends VRRP advertisement(s) for this router's
+    /// current vrid/addresses at `priority` -- 0 when a MASTER resigns, so
+    /// the BACKUPs take over after Skew_Time rather than waiting out
+    /// Master_Down_Interval (RFC 3768/5798 section 6.4.3). Always sends an
+    /// IPv4 advertisement; a v3 instance additionally sends an IPv6 one.
+    /// Multicast adverts go out over the family's mac-vlan; unicast ones go
+    /// to each peer of the family over `network_interface`, where the peers
+    /// are routed.
+    pub(crate) fn send_advertisement(&self, priority: u8) {
+        let adver_int_cs = self.advert_interval_cs;
+
+        let v4_pkt = self.advert_v4(priority);
+        if !self.is_unicast() {
+            let _ = network::send_vrrp_packet_v4(
+                &self.mac_vlan_interface_v4,
+                self.advert_src_v4(),
+                VRRP_MCAST_ADDR,
+                v4_pkt.clone(),
+            );
+        }
+        for peer in &self.unicast_peers {
+            if let IpAddr::V4(peer) = peer {
+                let _ = network::send_vrrp_packet_v4(
+                    &self.network_interface,
+                    self.advert_src_v4(),
+                    *peer,
+                    v4_pkt.clone(),
+                );
+            }
+        }
 
         if let (Some(v6_iface), Some(src_v6)) =
-            (&self.mac_vlan_interface_v6, self.primary_ip_v6)
+            (&self.mac_vlan_interface_v6, self.advert_src_v6())
         {
             let v6_pkt = VrrpPacket {
                 version: self.version,
                 vrid: self.vrid,
-                priority: self.priority,
+                priority,
                 adver_int_cs,
                 addresses: VrrpAddresses::V6(self.ipv6_addrs()),
+                auth: V2Auth::None,
             };
-            let _ = network::send_vrrp_packet_v6(v6_iface, src_v6, v6_pkt);
+            if !self.is_unicast() {
+                let _ = network::send_vrrp_packet_v6(
+                    v6_iface,
+                    src_v6,
+                    VRRP_V6_MCAST_ADDR,
+                    v6_pkt.clone(),
+                );
+            }
+            for peer in &self.unicast_peers {
+                if let IpAddr::V6(peer) = peer {
+                    let _ = network::send_vrrp_packet_v6(
+                        &self.network_interface,
+                        src_v6,
+                        *peer,
+                        v6_pkt.clone(),
+                    );
+                }
+            }
         }
     }
 
-    /// Sends a gratuitous ARP for each of this router's configured IPv4
-    /// addresses, announcing `interface_mac` as their new owner.
-    pub(crate) fn send_gratuitous_arps(&self, interface_mac: [u8; 6]) {
-        for ip in &self.ipv4_addresses {
-            let eth_frame = EthernetFrame {
-                dst_mac: [0xff; 6],
-                src_mac: interface_mac,
-                ethertype: 0x0806,
-            };
-            let arp_pkt = ArpPacket {
-                hw_type: 1,
-                proto_type: 0x0800,
-                hw_length: 6,
-                proto_length: 4,
-                operation: 1,
-                sender_hw_address: interface_mac,
-                sender_proto_address: ip.addr().octets(),
-                target_hw_address: [0xff; 6],
-                target_proto_address: ip.addr().octets(),
-            };
-            let arp_frame = ARPframe::new(eth_frame, arp_pkt);
-            network::send_packet_arp(&self.mac_vlan_interface_v4, arp_frame);
-        }
+    /// A gratuitous ARP for each of this router's configured IPv4
+    /// addresses, announcing `interface_mac` as their new owner. Sent as
+    /// requests, or as replies with `garp.reply`.
+    pub(crate) fn gratuitous_arps(
+        &self,
+        interface_mac: [u8; 6],
+    ) -> Vec<ARPframe> {
+        let (operation, target_hw_address) = if self.garp.reply {
+            (2, interface_mac)
+        } else {
+            (1, [0xff; 6])
+        };
+        self.ipv4_addresses
+            .iter()
+            .map(|ip| {
+                let eth_frame = EthernetFrame {
+                    dst_mac: [0xff; 6],
+                    src_mac: interface_mac,
+                    ethertype: 0x0806,
+                };
+                let arp_pkt = ArpPacket {
+                    hw_type: 1,
+                    proto_type: 0x0800,
+                    hw_length: 6,
+                    proto_length: 4,
+                    operation,
+                    sender_hw_address: interface_mac,
+                    sender_proto_address: ip.addr().octets(),
+                    target_hw_address,
+                    target_proto_address: ip.addr().octets(),
+                };
+                ARPframe::new(eth_frame, arp_pkt)
+            })
+            .collect()
     }
 
-    /// Sends an unsolicited (gratuitous) Neighbor Advertisement for each of
-    /// this router's configured IPv6 addresses -- IPv6 has no ARP, so this
-    /// is the equivalent of `send_gratuitous_arps` for the v6 side.
-    pub(crate) fn send_neighbor_advertisements(&self, interface_mac: [u8; 6]) {
-        let Some(v6_iface) = &self.mac_vlan_interface_v6 else {
-            return;
-        };
-        for ip in &self.ipv6_addresses {
-            let na = NdpNeighborAdvertisement {
+    /// An unsolicited (gratuitous) Neighbor Advertisement for each of this
+    /// router's configured IPv6 addresses -- IPv6 has no ARP, so this is
+    /// the equivalent of `gratuitous_arps` for the v6 side.
+    pub(crate) fn neighbor_advertisements(
+        &self,
+        interface_mac: [u8; 6],
+    ) -> Vec<NdpNeighborAdvertisement> {
+        self.ipv6_addresses
+            .iter()
+            .map(|ip| NdpNeighborAdvertisement {
                 target_address: ip.addr(),
                 target_link_addr: interface_mac,
                 override_flag: true,
-            };
-            network::send_neighbor_advertisement(v6_iface, ip.addr(), na);
-        }
+            })
+            .collect()
     }
 }
 
+/// Skew_Time and Master_Down_Interval (in seconds) for a router of the
+/// given version/priority/(master) advertisement interval.
+fn timer_intervals(
+    version: VrrpVersion,
+    priority: u8,
+    advert_interval_cs: u16,
+) -> (f32, f32) {
+    let advert_interval = advert_interval_cs as f32 / 100_f32;
+    let skew_time: f32 = match version {
+        // v2 (RFC 3768 section 6.2): a flat sub-second tiebreaker,
+        // independent of the advertisement interval.
+        VrrpVersion::V2 => (256_f32 - priority as f32) / 256_f32,
+        // v3 (RFC 5798 section 6.1): scales with the advertisement
+        // interval now that the interval itself has sub-second
+        // resolution. This is a real formula change, not just a unit
+        // relabeling -- do not collapse this back into the v2 arm.
+        VrrpVersion::V3 => {
+            ((256_f32 - priority as f32) * advert_interval) / 256_f32
+        }
+    };
+    let master_down_interval: f32 = (3_f32 * advert_interval) + skew_time;
+    (skew_time, master_down_interval)
+}
+
 pub(crate) struct VirtualRouterParams {
     pub(crate) name: String,
     pub(crate) vrid: u8,
@@ -186,7 +532,229 @@ pub(crate) struct VirtualRouterParams {
     pub(crate) ipv4_addresses: Vec<Ipv4Net>,
     pub(crate) ipv6_addresses: Vec<Ipv6Net>,
     pub(crate) priority: u8,
-    pub(crate) advert_interval: u8,
+    pub(crate) advert_interval_cs: u16,
     pub(crate) preempt_mode: bool,
+    pub(crate) preempt_delay: u16,
+    pub(crate) accept_mode: bool,
+    pub(crate) startup_delay: u16,
+    pub(crate) initial_state: InitialState,
+    pub(crate) track_interfaces: Vec<TrackInterface>,
+    pub(crate) track_scripts: Vec<TrackScript>,
+    pub(crate) track_routes: Vec<TrackRoute>,
+    pub(crate) sync_group: Option<SyncGroupHandle>,
+    pub(crate) unicast_peers: Vec<IpAddr>,
+    pub(crate) unicast_src_ip: Option<IpAddr>,
+    pub(crate) auth: V2Auth,
+    pub(crate) garp: GarpSettings,
     pub(crate) network_interface: String,
+    pub(crate) use_virtual_mac: bool,
+    pub(crate) link_backend: LinkBackend,
+}
+
+#[cfg(test)]
+pub(crate) mod tests {
+    use super::*;
+
+    pub(crate) fn sample(
+        version: VrrpVersion,
+        advert_interval_cs: u16,
+    ) -> VirtualRouter {
+        VirtualRouter::new(params(version, advert_interval_cs))
+    }
+
+    pub(crate) fn params(
+        version: VrrpVersion,
+        advert_interval_cs: u16,
+    ) -> VirtualRouterParams {
+        VirtualRouterParams {
+            name: "VR_1".to_string(),
+            vrid: 51,
+            version,
+            ipv4_addresses: vec!["192.168.100.100/24".parse().unwrap()],
+            ipv6_addresses: vec![],
+            priority: 100,
+            advert_interval_cs,
+            preempt_mode: true,
+            preempt_delay: 0,
+            accept_mode: true,
+            startup_delay: 0,
+            initial_state: InitialState::Auto,
+            track_interfaces: vec![],
+            track_scripts: vec![],
+            track_routes: vec![],
+            sync_group: None,
+            unicast_peers: vec![],
+            unicast_src_ip: None,
+            auth: V2Auth::None,
+            garp: GarpSettings::default(),
+            network_interface: "eth0".to_string(),
+            use_virtual_mac: true,
+            link_backend: LinkBackend::Macvlan,
+        }
+    }
+
+    #[test]
+    fn gratuitous_arps_go_out_as_requests_or_replies() {
+        let mut vr = sample(VrrpVersion::V3, 100);
+        let mac = [0x00, 0x00, 0x5e, 0x00, 0x01, 51];
+
+        let request = &vr.gratuitous_arps(mac)[0];
+        assert_eq!(request.opcode, 1u16.to_be());
+        assert_eq!(request.target_hw_addr, [0xff; 6]);
+        assert_eq!(request.target_proto_addr, [192, 168, 100, 100]);
+
+        vr.garp.reply = true;
+        let reply = &vr.gratuitous_arps(mac)[0];
+        assert_eq!(reply.opcode, 2u16.to_be());
+        assert_eq!(reply.target_hw_addr, mac);
+        assert_eq!(reply.sender_proto_addr, reply.target_proto_addr);
+    }
+
+    #[test]
+    fn v3_intervals_scale_with_sub_second_advert_interval() {
+        let vr = sample(VrrpVersion::V3, 10);
+        let skew = (156_f32 * 0.1) / 256_f32;
+
+        assert_eq!(vr.skew_time, skew);
+        assert_eq!(vr.master_down_interval, 0.3 + skew);
+    }
+
+    #[test]
+    fn v3_backup_recomputes_master_down_from_learned_interval() {
+        let mut vr = sample(VrrpVersion::V3, 100);
+        vr.learn_master_adver_interval(50);
+
+        assert_eq!(vr.master_adver_interval_cs, 50);
+        assert_eq!(vr.advert_interval_cs, 100);
+        let skew = (156_f32 * 0.5) / 256_f32;
+        assert_eq!(vr.skew_time, skew);
+        assert_eq!(vr.master_down_interval, 1.5 + skew);
+    }
+
+    #[test]
+    fn v2_ignores_advertised_interval() {
+        let mut vr = sample(VrrpVersion::V2, 100);
+        let before = vr.master_down_interval;
+        vr.learn_master_adver_interval(300);
+
+        assert_eq!(vr.master_adver_interval_cs, 100);
+        assert_eq!(vr.master_down_interval, before);
+    }
+
+    #[test]
+    fn status_shows_state_priority_and_accept_mode() {
+        let mut vr = sample(VrrpVersion::V3, 100);
+        vr.accept_mode = false;
+        vr.update_tracked("interface eth1", Some(40), false);
+        assert_eq!(
+            vr.status(),
+            "Init, priority 60 (configured 100), accept_mode: false"
+        );
+
+        vr.set_address_owner();
+        assert_eq!(
+            vr.status(),
+            "Init, priority 255 (configured 255), accept_mode: true, address owner"
+        );
+    }
+
+    #[test]
+    fn address_owner_runs_at_priority_255() {
+        let mut vr = sample(VrrpVersion::V3, 100);
+        vr.set_address_owner();
+
+        assert!(vr.is_address_owner());
+        assert_eq!(vr.priority, 255);
+        assert_eq!(vr.skew_time, 1_f32 / 256_f32);
+    }
+
+    #[test]
+    fn backup_owner_must_delay_or_not_preempt() {
+        let mut vr = sample(VrrpVersion::V3, 100);
+        vr.set_address_owner();
+        vr.initial_state = InitialState::Backup;
+        assert!(vr.owner_preempts_at_once());
+
+        vr.preempt_delay = 30;
+        assert!(!vr.owner_preempts_at_once());
+        vr.preempt_delay = 0;
+        vr.preempt_mode = false;
+        assert!(!vr.owner_preempts_at_once());
+    }
+
+    #[test]
+    fn initial_state_backup_overrides_address_ownership() {
+        let mut vr = sample(VrrpVersion::V3, 100);
+        vr.set_address_owner();
+        assert!(vr.starts_as_master());
+
+        vr.initial_state = InitialState::Backup;
+        assert!(!vr.starts_as_master());
+    }
+
+    #[test]
+    fn failing_tracked_interface_lowers_priority_and_skew() {
+        let mut vr = sample(VrrpVersion::V3, 100);
+        assert!(vr.update_tracked("interface eth1", Some(40), false));
+
+        assert_eq!(vr.effective_priority(), 60);
+        assert_eq!(vr.skew_time, 196_f32 / 256_f32);
+
+        assert!(vr.update_tracked("interface eth1", Some(40), true));
+        assert_eq!(vr.effective_priority(), 100);
+    }
+
+    #[test]
+    fn unicast_src_ip_only_applies_when_peering_by_unicast() {
+        let mut vr = sample(VrrpVersion::V3, 100);
+        vr.primary_ip = Ipv4Addr::new(10, 0, 0, 1);
+        vr.unicast_src_ip = Some(IpAddr::V4(Ipv4Addr::new(10, 0, 0, 100)));
+        assert_eq!(vr.advert_src_v4(), Ipv4Addr::new(10, 0, 0, 1));
+
+        vr.unicast_peers = vec![IpAddr::V4(Ipv4Addr::new(10, 0, 0, 2))];
+        assert!(vr.is_unicast());
+        assert_eq!(vr.advert_src_v4(), Ipv4Addr::new(10, 0, 0, 100));
+        assert_eq!(vr.advert_src_v6(), None);
+    }
+
+    #[test]
+    fn equal_priority_tie_goes_to_the_higher_primary_address() {
+        let mut vr = sample(VrrpVersion::V3, 100);
+        vr.primary_ip = Ipv4Addr::new(10, 0, 0, 5);
+
+        let higher = IpAddr::V4(Ipv4Addr::new(10, 0, 0, 9));
+        let lower = IpAddr::V4(Ipv4Addr::new(10, 0, 0, 1));
+        assert!(vr.is_outranked_by(100, higher));
+        assert!(!vr.is_outranked_by(100, lower));
+
+        // Priority decides before the address does.
+        assert!(vr.is_outranked_by(101, lower));
+        assert!(!vr.is_outranked_by(99, higher));
+    }
+
+    #[test]
+    fn v6_tiebreak_uses_the_v6_primary_address() {
+        let mut vr = sample(VrrpVersion::V3, 100);
+        vr.primary_ip_v6 = Some("fe80::5".parse().unwrap());
+
+        assert!(vr.is_outranked_by(100, "fe80::9".parse().unwrap()));
+        assert!(!vr.is_outranked_by(100, "fe80::1".parse().unwrap()));
+    }
+
+    #[test]
+    fn host_addresses_are_never_added_or_deleted() {
+        let mut params = params(VrrpVersion::V3, 100);
+        params
+            .ipv4_addresses
+            .push("192.168.100.101/24".parse().unwrap());
+        params.ipv6_addresses = vec!["fd00::1/64".parse().unwrap()];
+        let mut vr = VirtualRouter::new(params);
+        vr.host_addresses = vec![
+            "192.168.100.100".parse().unwrap(),
+            "fd00::1".parse().unwrap(),
+        ];
+
+        assert_eq!(vr.str_ipv4_addresses(), vec!["192.168.100.101/24"]);
+        assert!(vr.str_ipv6_addresses().is_empty());
+    }
 }
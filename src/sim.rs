@@ -0,0 +1,543 @@
+//! A simulated LAN for failover tests: several virtual routers exchanging
+//! adverts over a shared segment under a virtual clock, with no sockets,
+//! netlink or tokio involved. Each router runs `VirtualRouter::handle`
+//! just as its actor would; every advert it sends is encoded with
+//! `VrrpPacket::encode`, put on the wire, and decoded and validated (see
+//! `accept_advert`) by each of the others, and time only moves when the
+//! test runs the clock -- straight to the next timer or delivery -- so a
+//! run is reproducible. A fault rule decides what happens to each advert
+//! on its way to each receiver: delivered, dropped, delayed or
+//! duplicated.
+//!
+//! Only the IPv4 multicast side is modelled. Sync groups aren't: a
+//! router's `Publish`es go nowhere.
+use std::collections::BTreeMap;
+use std::net::IpAddr;
+use std::time::{Duration, Instant};
+
+use bytes::BytesMut;
+
+use crate::packet::{VRRP_MCAST_ADDR, VrrpPacket};
+use crate::pkt::handlers::accept_advert;
+use crate::router::VirtualRouter;
+use crate::state_machine::{Action, Event, State};
+
+/// How long an undisturbed advert takes to reach the other routers.
+const LATENCY: Duration = Duration::from_millis(1);
+
+/// What a fault rule does with an advert on its way to one receiver.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) enum Fate {
+    Deliver,
+    Drop,
+    /// Delivered this much later than usual.
+    Delay(Duration),
+    /// Delivered twice.
+    Duplicate,
+}
+
+/// An advert on its way from one router to another, as a fault rule sees
+/// it. `at` is when it was sent.
+#[derive(Debug, Clone, Copy)]
+pub(crate) struct Sent {
+    pub(crate) at: Duration,
+    pub(crate) from: usize,
+    pub(crate) to: usize,
+    pub(crate) priority: u8,
+}
+
+/// A router entering `state`, `at` into the run.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub(crate) struct Transition {
+    pub(crate) at: Duration,
+    pub(crate) router: usize,
+    pub(crate) state: State,
+}
+
+struct Node {
+    vrouter: VirtualRouter,
+    wakeup: Option<Instant>,
+    /// Whether the virtual addresses would be on its interface.
+    holds_addresses: bool,
+    /// Whether frames sent to the virtual MAC would be dropped on arrival.
+    discards_virtual_mac: bool,
+}
+
+struct Frame {
+    to: usize,
+    src: IpAddr,
+    data: BytesMut,
+}
+
+pub(crate) struct Lan {
+    start: Instant,
+    now: Instant,
+    nodes: Vec<Node>,
+    /// In delivery order; the sequence number keeps frames due at the same
+    /// instant in the order they were sent.
+    wire: BTreeMap<(Instant, u64), Frame>,
+    sent: u64,
+    faults: Box<dyn FnMut(&Sent) -> Fate>,
+    timeline: Vec<Transition>,
+}
+
+impl Lan {
+    pub(crate) fn new() -> Self {
+        let start = Instant::now();
+        Self {
+            start,
+            now: start,
+            nodes: vec![],
+            wire: BTreeMap::new(),
+            sent: 0,
+            faults: Box::new(|_| Fate::Deliver),
+            timeline: vec![],
+        }
+    }
+
+    /// Connects `vrouter`, still in INIT, to the LAN; returns its index.
+    pub(crate) fn add(&mut self, vrouter: VirtualRouter) -> usize {
+        self.nodes.push(Node {
+            vrouter,
+            wakeup: None,
+            holds_addresses: false,
+            discards_virtual_mac: false,
+        });
+        self.nodes.len() - 1
+    }
+
+    /// Replaces the fault rule, which until now delivered everything.
+    pub(crate) fn set_faults(
+        &mut self,
+        faults: impl FnMut(&Sent) -> Fate + 'static,
+    ) {
+        self.faults = Box::new(faults);
+    }
+
+    /// Hands `event` to `router` now, e.g. `Event::Startup`.
+    pub(crate) fn inject(&mut self, router: usize, event: Event) {
+        self.step(router, event);
+    }
+
+    /// Starts every router, in the order they were added.
+    pub(crate) fn start_all(&mut self) {
+        for router in 0..self.nodes.len() {
+            self.step(router, Event::Startup);
+        }
+    }
+
+    /// Runs the clock forward by `duration`, firing timers and delivering
+    /// adverts as they fall due. At the same instant, deliveries go before
+    /// timers, and lower-numbered routers before higher.
+    pub(crate) fn run_for(&mut self, duration: Duration) {
+        let until = self.now + duration;
+        loop {
+            let frame_due = self.wire.keys().next().map(|&(at, _)| at);
+            let timer_due = self
+                .nodes
+                .iter()
+                .enumerate()
+                .filter_map(|(i, node)| node.wakeup.map(|at| (at, i)))
+                .min();
+            match (frame_due, timer_due) {
+                (Some(at), timer)
+                    if at <= until && timer.is_none_or(|(t, _)| at <= t) =>
+                {
+                    self.now = at;
+                    let (_, frame) = self.wire.pop_first().unwrap();
+                    self.deliver(frame);
+                }
+                (_, Some((at, router))) if at <= until => {
+                    self.now = at;
+                    self.step(router, Event::Timer);
+                }
+                _ => break,
+            }
+        }
+        self.now = until;
+    }
+
+    pub(crate) fn state(&self, router: usize) -> State {
+        self.nodes[router].vrouter.fsm.state
+    }
+
+    /// For changing a router's settings or tracked objects mid-run.
+    pub(crate) fn router_mut(&mut self, router: usize) -> &mut VirtualRouter {
+        &mut self.nodes[router].vrouter
+    }
+
+    /// The routers holding the virtual addresses; more than one is a split
+    /// brain.
+    pub(crate) fn holders(&self) -> Vec<usize> {
+        (0..self.nodes.len())
+            .filter(|&i| self.nodes[i].holds_addresses)
+            .collect()
+    }
+
+    /// The routers that would take in a frame sent to the virtual MAC,
+    /// rather than discard it.
+    pub(crate) fn virtual_mac_takers(&self) -> Vec<usize> {
+        (0..self.nodes.len())
+            .filter(|&i| !self.nodes[i].discards_virtual_mac)
+            .collect()
+    }
+
+    /// Every state change so far, in order.
+    pub(crate) fn timeline(&self) -> &[Transition] {
+        &self.timeline
+    }
+
+    fn elapsed(&self) -> Duration {
+        self.now - self.start
+    }
+
+    fn step(&mut self, router: usize, event: Event) {
+        let node = &mut self.nodes[router];
+        let before = node.vrouter.fsm.state;
+        let actions = node.vrouter.handle(event, self.now);
+        for action in actions {
+            let node = &mut self.nodes[router];
+            match action {
+                Action::SendAdvert { priority } => {
+                    self.transmit(router, priority);
+                }
+                Action::AddAddresses => node.holds_addresses = true,
+                Action::DeleteAddresses => node.holds_addresses = false,
+                Action::DiscardVirtualMac => node.discards_virtual_mac = true,
+                Action::AcceptVirtualMac => node.discards_virtual_mac = false,
+                Action::Announce | Action::Publish(_) => {}
+                Action::ArmTimer(at) => node.wakeup = at,
+            }
+        }
+        let state = self.nodes[router].vrouter.fsm.state;
+        if state != before {
+            self.timeline.push(Transition {
+                at: self.elapsed(),
+                router,
+                state,
+            });
+        }
+    }
+
+    fn transmit(&mut self, from: usize, priority: u8) {
+        let vrouter = &self.nodes[from].vrouter;
+        let src = IpAddr::V4(vrouter.advert_src_v4());
+        let data = vrouter.advert_v4(priority).encode(src);
+        for to in (0..self.nodes.len()).filter(|&to| to != from) {
+            let sent = Sent {
+                at: self.elapsed(),
+                from,
+                to,
+                priority,
+            };
+            let (copies, delay) = match (self.faults)(&sent) {
+                Fate::Deliver => (1, Duration::ZERO),
+                Fate::Drop => (0, Duration::ZERO),
+                Fate::Delay(delay) => (1, delay),
+                Fate::Duplicate => (2, Duration::ZERO),
+            };
+            for _ in 0..copies {
+                let at = self.now + LATENCY + delay;
+                let frame = Frame {
+                    to,
+                    src,
+                    data: data.clone(),
+                };
+                self.wire.insert((at, self.sent), frame);
+                self.sent += 1;
+            }
+        }
+    }
+
+    fn deliver(&mut self, frame: Frame) {
+        let dst = IpAddr::V4(VRRP_MCAST_ADDR);
+        let Ok(packet) = VrrpPacket::decode(&frame.data, frame.src, dst) else {
+            return;
+        };
+        let vrouter = &self.nodes[frame.to].vrouter;
+        // `dispatch`'s job on a real interface.
+        if packet.vrid != vrouter.vrid {
+            return;
+        }
+        if let Some(advert) = accept_advert(&packet, frame.src, 255, vrouter) {
+            self.step(frame.to, Event::Advert(advert));
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::cell::Cell;
+    use std::net::Ipv4Addr;
+    use std::rc::Rc;
+
+    use super::*;
+    use crate::VrrpVersion;
+    use crate::router::VirtualRouterParams;
+    use crate::router::tests::params;
+
+    /// A v3 router with 1s adverts at `priority`, sending from 10.0.0.`host`.
+    fn router(priority: u8, host: u8) -> VirtualRouter {
+        let mut vr = VirtualRouter::new(VirtualRouterParams {
+            name: format!("VR_{host}"),
+            priority,
+            ..params(VrrpVersion::V3, 100)
+        });
+        vr.primary_ip = Ipv4Addr::new(10, 0, 0, host);
+        vr
+    }
+
+    fn secs(secs: f32) -> Duration {
+        Duration::from_secs_f32(secs)
+    }
+
+    fn at(at: Duration, router: usize, state: State) -> Transition {
+        Transition { at, router, state }
+    }
+
+    #[test]
+    fn higher_priority_router_takes_over_and_the_other_stays_backup() {
+        let mut lan = Lan::new();
+        let low = lan.add(router(100, 1));
+        let high = lan.add(router(200, 2));
+        let master_down = secs(lan.router_mut(high).master_down_interval);
+
+        lan.start_all();
+        lan.run_for(secs(30.0));
+
+        assert_eq!(
+            lan.timeline(),
+            [
+                at(Duration::ZERO, low, State::Backup),
+                at(Duration::ZERO, high, State::Backup),
+                at(master_down, high, State::Master),
+            ]
+        );
+        assert_eq!(lan.holders(), [high]);
+    }
+
+    #[test]
+    fn backup_takes_over_when_the_masters_adverts_are_lost() {
+        let mut lan = Lan::new();
+        let master = lan.add(router(200, 1));
+        let backup = lan.add(router(100, 2));
+
+        // Lose everything the master sends the backup from 10s on, noting
+        // when the last advert that got through was sent.
+        let cut = secs(10.0);
+        let last_sent = Rc::new(Cell::new(Duration::ZERO));
+        let noted = Rc::clone(&last_sent);
+        lan.set_faults(move |sent| {
+            if sent.from != master || sent.to != backup {
+                Fate::Deliver
+            } else if sent.at >= cut {
+                Fate::Drop
+            } else {
+                noted.set(sent.at);
+                Fate::Deliver
+            }
+        });
+        lan.start_all();
+        lan.run_for(secs(10.0));
+        assert_eq!(lan.holders(), [master]);
+        lan.run_for(secs(10.0));
+
+        // The backup gives up one Master_Down_Interval after hearing it.
+        let last_heard = last_sent.get() + LATENCY;
+        let master_down = secs(lan.router_mut(backup).master_down_interval);
+        assert_eq!(
+            lan.timeline().last(),
+            Some(&at(last_heard + master_down, backup, State::Master))
+        );
+        assert_eq!(lan.holders(), [master, backup]);
+    }
+
+    #[test]
+    fn split_brain_heals_once_adverts_get_through_again() {
+        let mut lan = Lan::new();
+        let master = lan.add(router(200, 1));
+        let backup = lan.add(router(100, 2));
+        lan.set_faults(move |sent| {
+            let partitioned = sent.at < secs(10.0);
+            if partitioned {
+                Fate::Drop
+            } else {
+                Fate::Deliver
+            }
+        });
+
+        lan.start_all();
+        lan.run_for(secs(9.0));
+        assert_eq!(lan.holders(), [master, backup]);
+
+        lan.run_for(secs(5.0));
+        assert_eq!(lan.state(master), State::Master);
+        assert_eq!(lan.state(backup), State::Backup);
+        assert_eq!(lan.holders(), [master]);
+    }
+
+    #[test]
+    fn equal_priorities_are_settled_by_the_higher_address() {
+        let mut lan = Lan::new();
+        let lower = lan.add(router(100, 1));
+        let higher = lan.add(router(100, 2));
+
+        lan.start_all();
+        lan.run_for(secs(30.0));
+
+        // Same priority, same skew: both time out together, and the lower
+        // address yields to the other's first advert.
+        let master_down = secs(lan.router_mut(lower).master_down_interval);
+        assert_eq!(
+            &lan.timeline()[2..],
+            [
+                at(master_down, lower, State::Master),
+                at(master_down, higher, State::Master),
+                at(master_down + LATENCY, lower, State::Backup),
+            ]
+        );
+        assert_eq!(lan.holders(), [higher]);
+    }
+
+    #[test]
+    fn duplicated_adverts_change_nothing() {
+        let run = |faults: fn(&Sent) -> Fate| {
+            let mut lan = Lan::new();
+            lan.add(router(100, 1));
+            lan.add(router(200, 2));
+            lan.add(router(150, 3));
+            lan.set_faults(faults);
+            lan.start_all();
+            lan.run_for(secs(30.0));
+            (lan.timeline().to_vec(), lan.holders())
+        };
+
+        assert_eq!(run(|_| Fate::Duplicate), run(|_| Fate::Deliver));
+    }
+
+    #[test]
+    fn late_higher_priority_router_preempts() {
+        let mut lan = Lan::new();
+        let first = lan.add(router(100, 1));
+        let late = lan.add(router(200, 2));
+        lan.inject(first, Event::Startup);
+        lan.run_for(secs(10.0));
+        assert_eq!(lan.state(first), State::Master);
+
+        lan.inject(late, Event::Startup);
+        lan.run_for(secs(10.0));
+
+        assert_eq!(lan.state(late), State::Master);
+        assert_eq!(lan.state(first), State::Backup);
+        assert_eq!(lan.holders(), [late]);
+    }
+
+    #[test]
+    fn without_preempt_a_late_higher_priority_router_stays_backup() {
+        let mut lan = Lan::new();
+        let first = lan.add(router(100, 1));
+        let mut late_router = router(200, 2);
+        late_router.preempt_mode = false;
+        let late = lan.add(late_router);
+        lan.inject(first, Event::Startup);
+        lan.run_for(secs(10.0));
+
+        lan.inject(late, Event::Startup);
+        lan.run_for(secs(30.0));
+
+        assert_eq!(lan.state(first), State::Master);
+        assert_eq!(lan.state(late), State::Backup);
+    }
+
+    #[test]
+    fn adverts_delayed_past_master_down_cause_one_spurious_takeover() {
+        let mut lan = Lan::new();
+        let master = lan.add(router(200, 1));
+        let backup = lan.add(router(100, 2));
+        lan.start_all();
+        lan.run_for(secs(10.0));
+
+        // Every advert now arrives 5s late: the backup times out once in
+        // the gap, then yields to the first late one and keeps hearing
+        // them, a second apart.
+        lan.set_faults(|_| Fate::Delay(secs(5.0)));
+        lan.run_for(secs(30.0));
+
+        let takeovers = lan
+            .timeline()
+            .iter()
+            .filter(|t| t.router == backup && t.state == State::Master)
+            .count();
+        assert_eq!(takeovers, 1);
+        assert_eq!(lan.state(backup), State::Backup);
+        assert_eq!(lan.holders(), [master]);
+    }
+
+    #[test]
+    fn master_releasing_hands_over_after_skew_time() {
+        let mut lan = Lan::new();
+        let master = lan.add(router(200, 1));
+        let backup = lan.add(router(100, 2));
+        lan.start_all();
+        lan.run_for(secs(10.0));
+
+        lan.inject(master, Event::Release);
+        lan.run_for(secs(10.0));
+
+        let skew = secs(lan.router_mut(backup).skew_time);
+        assert_eq!(
+            &lan.timeline()[3..],
+            [
+                at(secs(10.0), master, State::Backup),
+                at(secs(10.0) + LATENCY + skew, backup, State::Master),
+            ]
+        );
+        assert_eq!(lan.holders(), [backup]);
+    }
+
+    #[test]
+    fn only_the_master_takes_in_frames_sent_to_the_virtual_mac() {
+        let mut lan = Lan::new();
+        let master = lan.add(router(200, 1));
+        let backup = lan.add(router(100, 2));
+        lan.start_all();
+        lan.run_for(secs(10.0));
+        assert_eq!(lan.virtual_mac_takers(), [master]);
+
+        lan.inject(master, Event::Release);
+        lan.run_for(secs(10.0));
+        assert_eq!(lan.virtual_mac_takers(), [backup]);
+    }
+
+    #[test]
+    fn lost_resignation_leaves_the_backup_to_time_out() {
+        let mut lan = Lan::new();
+        let master = lan.add(router(200, 1));
+        let backup = lan.add(router(100, 2));
+        let last_sent = Rc::new(Cell::new(Duration::ZERO));
+        let noted = Rc::clone(&last_sent);
+        lan.set_faults(move |sent| {
+            if sent.priority == 0 {
+                return Fate::Drop;
+            }
+            if sent.from == master {
+                noted.set(sent.at);
+            }
+            Fate::Deliver
+        });
+        lan.start_all();
+        lan.run_for(secs(10.0));
+
+        lan.inject(master, Event::Release);
+        lan.run_for(secs(10.0));
+
+        // Last heard from the master's final advert before it released.
+        let last_heard = last_sent.get() + LATENCY;
+        let master_down = secs(lan.router_mut(backup).master_down_interval);
+        assert_eq!(
+            lan.timeline().last(),
+            Some(&at(last_heard + master_down, backup, State::Master))
+        );
+        assert_eq!(lan.holders(), [backup]);
+    }
+}
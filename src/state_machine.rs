@@ -1,30 +1,46 @@
+use std::net::IpAddr;
 use std::time::{Duration, Instant};
 
+use crate::router::VirtualRouter;
+use crate::sync;
+
 #[derive(Debug, Clone, Default)]
 pub struct VirtualRouterMachine {
     pub(crate) timer: Timer,
+    /// Runs alongside `timer` while a higher-priority BACKUP holds off
+    /// preempting a lower-priority MASTER (`preempt_delay`).
+    pub(crate) preempt_timer: Timer,
+    /// Priority and source of the MASTER advert that armed
+    /// `preempt_timer`; a change in the MASTER's priority restarts the
+    /// wait, and the preempt only goes ahead if we still outrank it.
+    pub(crate) preempt_against: Option<(u8, IpAddr)>,
     pub(crate) state: State,
-    pub(crate) event: Event,
 }
 
 impl VirtualRouterMachine {
-    pub fn set_advert_timer(&mut self, duration: f32) {
+    pub fn set_advert_timer(&mut self, duration: f32, now: Instant) {
         self.timer = Timer {
             t_type: TimerType::Adver,
             remaining_time: duration,
-            waiting_for: Some(
-                Instant::now() + Duration::from_secs_f32(duration),
-            ),
+            waiting_for: Some(now + Duration::from_secs_f32(duration)),
         };
     }
 
-    pub fn set_master_down_timer(&mut self, duration: f32) {
+    pub fn set_master_down_timer(&mut self, duration: f32, now: Instant) {
         self.timer = Timer {
             t_type: TimerType::MasterDown,
             remaining_time: duration,
-            waiting_for: Some(
-                Instant::now() + Duration::from_secs_f32(duration),
-            ),
+            waiting_for: Some(now + Duration::from_secs_f32(duration)),
+        };
+    }
+
+    /// Holds the instance in INIT until `duration` elapses, at which point
+    /// it makes the startup transition.
+    pub fn set_startup_timer(&mut self, duration: f32, now: Instant) {
+        self.timer = Timer {
+            t_type: TimerType::Startup,
+            remaining_time: duration,
+            waiting_for: Some(now + Duration::from_secs_f32(duration)),
         };
     }
 
@@ -35,6 +51,38 @@ impl VirtualRouterMachine {
             waiting_for: None,
         };
     }
+
+    pub fn set_preempt_timer(
+        &mut self,
+        duration: f32,
+        master_priority: u8,
+        master_src: IpAddr,
+        now: Instant,
+    ) {
+        self.preempt_timer = Timer {
+            t_type: TimerType::Preempt,
+            remaining_time: duration,
+            waiting_for: Some(now + Duration::from_secs_f32(duration)),
+        };
+        self.preempt_against = Some((master_priority, master_src));
+    }
+
+    pub fn disable_preempt_timer(&mut self) {
+        if self.preempt_against.is_none() {
+            return;
+        }
+        self.preempt_timer = Timer::default();
+        self.preempt_against = None;
+    }
+
+    /// The earliest deadline across every armed timer, i.e. when the
+    /// router's task next has to wake up.
+    pub(crate) fn next_deadline(&self) -> Option<Instant> {
+        [self.timer.waiting_for, self.preempt_timer.waiting_for]
+            .into_iter()
+            .flatten()
+            .min()
+    }
 }
 
 #[derive(Default, Debug, Clone, Copy, PartialEq)]
@@ -43,6 +91,9 @@ pub(crate) enum State {
     Init,
     Backup,
     Master,
+    /// A tracked object without a weight is down: holds no addresses and
+    /// doesn't compete for MASTER until it recovers.
+    Fault,
 }
 
 #[derive(Default, Debug, Clone, Copy, PartialEq)]
@@ -59,48 +110,490 @@ pub(crate) enum TimerType {
 
     MasterDown,
     Adver,
+    Preempt,
+    Startup,
 }
 
-#[derive(Debug, Clone, Copy, PartialEq, Default)]
-#[allow(dead_code)]
+/// Everything the state machine reacts to; see `VirtualRouter::handle`.
+#[derive(Debug, Clone, Copy, PartialEq)]
 pub(crate) enum Event {
-    #[default]
+    /// Leaving INIT, straight away or once `startup_delay` is up.
     Startup,
-    Null,
     Shutdown,
-    MasterDown,
+    /// An advert for this router that passed the receive checks (see
+    /// `pkt::handlers::accept_advert`).
+    Advert(Advert),
+    /// The clock reached the deadline last asked for with
+    /// `Action::ArmTimer`: fires whichever timers are due.
+    Timer,
+    /// A tracked object changed health, moving the effective priority
+    /// and/or the FAULT condition.
+    PriorityChange,
+    /// Another member of this instance's sync group moved to this state.
+    Sync(State),
+    /// Operator request (SIGUSR1) for a MASTER to hand over to a BACKUP
+    /// while staying up.
+    Release,
+}
+
+/// The parts of a received advert the state machine acts on.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub(crate) struct Advert {
+    pub(crate) priority: u8,
+    pub(crate) adver_int_cs: u16,
+    /// Where it came from, for the equal-priority tiebreak.
+    pub(crate) src: IpAddr,
+}
+
+/// What has to be done about an `Event`, in order. The state machine only
+/// decides; carrying these out (see `actor`) is what touches sockets and
+/// netlink.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub(crate) enum Action {
+    /// An advert in each family at this priority; 0 resigns mastership.
+    SendAdvert {
+        priority: u8,
+    },
+    /// Take on the virtual IP(s), along with any Accept_Mode filter. Never
+    /// asked of the address owner, nor is `DeleteAddresses`.
+    AddAddresses,
+    DeleteAddresses,
+    /// (Re)start the gratuitous ARPs/unsolicited NAs.
+    Announce,
+    /// Start discarding frames sent to the virtual MAC, as a BACKUP (or
+    /// FAULT) must (RFC 3768/5798 section 6).
+    DiscardVirtualMac,
+    /// Stop discarding them, on becoming MASTER.
+    AcceptVirtualMac,
+    /// Report a transition made of the router's own accord (never one
+    /// forced by `Event::Sync`) to the rest of its sync group.
+    Publish(State),
+    /// Feed back `Event::Timer` at this instant, instead of any earlier
+    /// one; `None` once no timer is armed.
+    ArmTimer(Option<Instant>),
+}
+
+/// The RFC 3768/5798 state machine proper (sections 6.3-6.5). Lives on
+/// the router rather than `VirtualRouterMachine` because its decisions turn
+/// on the router's priority, intervals and preempt settings.
+impl VirtualRouter {
+    /// Runs `event` through the state machine at `now`, updating the state
+    /// and timers and returning what has to be done about it.
+    pub(crate) fn handle(&mut self, event: Event, now: Instant) -> Vec<Action> {
+        let wakeup = self.fsm.next_deadline();
+        let before = self.fsm.state;
+        let mut actions = vec![];
+        match event {
+            Event::Startup => self.begin(now, &mut actions),
+            Event::Shutdown => self.shut_down(&mut actions),
+            Event::Advert(advert) => self.receive(advert, now, &mut actions),
+            Event::Timer => self.fire_timers(now, &mut actions),
+            Event::PriorityChange => self.reprioritise(now, &mut actions),
+            Event::Sync(forced) => self.follow(forced, now, &mut actions),
+            Event::Release => self.release(now, &mut actions),
+        }
+        match (before, self.fsm.state) {
+            (State::Master, State::Master) => {}
+            (_, State::Master) => actions.push(Action::AcceptVirtualMac),
+            (State::Init | State::Master, State::Backup | State::Fault) => {
+                actions.push(Action::DiscardVirtualMac);
+            }
+            _ => {}
+        }
+        let next = self.fsm.next_deadline();
+        if next != wakeup {
+            actions.push(Action::ArmTimer(next));
+        }
+        // The owner's virtual IP(s) are the host's own addresses on the
+        // parent: never ours to copy onto a mac-vlan, let alone remove.
+        if self.is_address_owner() {
+            actions.retain(|action| {
+                !matches!(
+                    action,
+                    Action::AddAddresses | Action::DeleteAddresses
+                )
+            });
+        }
+        actions
+    }
+
+    /// With a `startup_delay` the instance stays in INIT, only listening,
+    /// until its startup timer fires the transition.
+    fn begin(&mut self, now: Instant, actions: &mut Vec<Action>) {
+        if self.startup_delay == 0 {
+            self.start(now, actions);
+        } else if self.fsm.state == State::Init
+            && self.fsm.timer.t_type != TimerType::Startup
+        {
+            let delay = self.startup_delay;
+            self.fsm.set_startup_timer(delay as f32, now);
+            log::info!("({}) holding in INIT for {delay}s", self.name);
+        }
+    }
+
+    fn start(&mut self, now: Instant, actions: &mut Vec<Action>) {
+        if self.fsm.state != State::Init {
+            return;
+        }
+        self.fsm.disable_timer();
+        if self.tracked.is_faulted() {
+            self.fsm.state = State::Fault;
+            log::info!("({}) transitioned to FAULT (init)", self.name);
+            actions.push(Action::Publish(State::Fault));
+        } else if self.starts_as_master() {
+            self.become_master(now, actions);
+            log::info!("({}) transitioned to MASTER (init)", self.name);
+            actions.push(Action::Publish(State::Master));
+        } else {
+            // Delete virtual IP(s).
+            actions.push(Action::DeleteAddresses);
+            self.fsm
+                .set_master_down_timer(self.master_down_interval, now);
+            self.fsm.state = State::Backup;
+            log::info!("({}) transitioned to BACKUP (init)", self.name);
+            actions.push(Action::Publish(State::Backup));
+        }
+    }
+
+    fn shut_down(&mut self, actions: &mut Vec<Action>) {
+        self.fsm.disable_preempt_timer();
+        match self.fsm.state {
+            State::Backup => {
+                self.fsm.disable_timer();
+                self.fsm.state = State::Init;
+            }
+            State::Master => {
+                self.fsm.disable_timer();
+                actions.push(Action::SendAdvert { priority: 0 });
+                actions.push(Action::DeleteAddresses);
+                self.fsm.state = State::Init;
+            }
+            State::Init | State::Fault => {}
+        }
+    }
+
+    /// BACKUP -> MASTER: advertise, announce ownership, take over the
+    /// virtual IP(s) and start sending periodic adverts.
+    fn become_master(&mut self, now: Instant, actions: &mut Vec<Action>) {
+        self.fsm.disable_preempt_timer();
+        self.released = false;
+        // Send ADVERTISEMENT then announce ownership.
+        actions.push(Action::SendAdvert {
+            priority: self.effective_priority(),
+        });
+        actions.push(Action::Announce);
+        actions.push(Action::AddAddresses);
+        self.fsm.set_advert_timer(self.advert_interval_secs(), now);
+        self.fsm.state = State::Master;
+    }
+
+    /// MASTER -> BACKUP: give up the virtual IP(s) and wait out
+    /// Master_Down_Interval for the new MASTER's adverts.
+    fn become_backup(&mut self, now: Instant, actions: &mut Vec<Action>) {
+        actions.push(Action::DeleteAddresses);
+        self.fsm
+            .set_master_down_timer(self.master_down_interval, now);
+        self.fsm.state = State::Backup;
+    }
+
+    fn fire_timers(&mut self, now: Instant, actions: &mut Vec<Action>) {
+        let expired =
+            |timer: Timer| timer.waiting_for.is_some_and(|w| w <= now);
+
+        // `preempt_delay` is up while still outranking the MASTER.
+        if expired(self.fsm.preempt_timer) {
+            let against = self.fsm.preempt_against;
+            self.fsm.disable_preempt_timer();
+            let outranks = against.is_some_and(|(priority, src)| {
+                !self.is_outranked_by(priority, src)
+            });
+            if self.fsm.state == State::Backup && outranks {
+                self.become_master(now, actions);
+                log::info!("({}) Transitioned to MASTER", self.name);
+                actions.push(Action::Publish(State::Master));
+            }
+        }
+
+        let timer = self.fsm.timer;
+        if !expired(timer) {
+            return;
+        }
+        match timer.t_type {
+            TimerType::MasterDown => {
+                self.fsm.disable_timer();
+                if self.fsm.state == State::Backup {
+                    self.become_master(now, actions);
+                    log::info!("({}) Transitioned to MASTER", self.name);
+                    actions.push(Action::Publish(State::Master));
+                }
+            }
+            TimerType::Startup => {
+                self.fsm.disable_timer();
+                self.start(now, actions);
+            }
+            TimerType::Adver => {
+                actions.push(Action::SendAdvert {
+                    priority: self.effective_priority(),
+                });
+                self.fsm.set_advert_timer(self.advert_interval_secs(), now);
+            }
+            TimerType::Preempt | TimerType::Null => {}
+        }
+    }
+
+    fn receive(
+        &mut self,
+        advert: Advert,
+        now: Instant,
+        actions: &mut Vec<Action>,
+    ) {
+        match self.fsm.state {
+            State::Backup => {
+                if advert.priority == 0 {
+                    self.fsm.disable_preempt_timer();
+                    self.fsm.set_master_down_timer(self.skew_time, now);
+                } else if !self.preempt_mode
+                    || self.released
+                    || self.is_outranked_by(advert.priority, advert.src)
+                {
+                    self.fsm.disable_preempt_timer();
+                    self.learn_master_adver_interval(advert.adver_int_cs);
+                    let m_down_interval = self.master_down_interval;
+                    self.fsm.set_master_down_timer(m_down_interval, now);
+                } else if self.preempt_delay > 0 {
+                    // Still hearing from the MASTER, so keep it alive while
+                    // the preempt_delay runs. A change in its priority
+                    // restarts the wait rather than carrying on with the old
+                    // one.
+                    let m_down_interval = self.master_down_interval;
+                    self.fsm.set_master_down_timer(m_down_interval, now);
+                    let against = self.fsm.preempt_against;
+                    if against.map(|(priority, _)| priority)
+                        != Some(advert.priority)
+                    {
+                        self.fsm.disable_preempt_timer();
+                        self.fsm.set_preempt_timer(
+                            self.preempt_delay as f32,
+                            advert.priority,
+                            advert.src,
+                            now,
+                        );
+                        log::info!(
+                            "({}) outranks MASTER (priority {}); preempting in {}s",
+                            self.name,
+                            advert.priority,
+                            self.preempt_delay
+                        );
+                    }
+                } else {
+                    // We outrank the MASTER -- which includes an
+                    // equal-priority one with a lower primary address -- so
+                    // preempt it.
+                    self.become_master(now, actions);
+                    log::info!("({}) transitioned to MASTER", self.name);
+                    actions.push(Action::Publish(State::Master));
+                }
+            }
+            State::Master => {
+                if advert.priority == 0 {
+                    // Another router resigned: let the BACKUPs hear from us
+                    // straight away.
+                    actions.push(Action::SendAdvert {
+                        priority: self.effective_priority(),
+                    });
+                    self.fsm.set_advert_timer(self.advert_interval_secs(), now);
+                } else if self.is_outranked_by(advert.priority, advert.src) {
+                    // A higher priority, or an equal one from a higher
+                    // primary address: yield to it.
+                    self.become_backup(now, actions);
+                    log::info!("({}) transitioned to BACKUP", self.name);
+                    actions.push(Action::Publish(State::Backup));
+                }
+            }
+            State::Init | State::Fault => {}
+        }
+    }
+
+    fn reprioritise(&mut self, now: Instant, actions: &mut Vec<Action>) {
+        let faulted = self.tracked.is_faulted();
+        match self.fsm.state {
+            State::Master | State::Backup if faulted => {
+                if self.fsm.state == State::Master {
+                    actions.push(Action::DeleteAddresses);
+                }
+                self.fsm.disable_timer();
+                self.fsm.disable_preempt_timer();
+                self.fsm.state = State::Fault;
+                let failing: Vec<&str> = self.tracked.failing().collect();
+                log::info!(
+                    "({}) transitioned to FAULT ({} failing)",
+                    self.name,
+                    failing.join(", ")
+                );
+                actions.push(Action::Publish(State::Fault));
+            }
+            State::Fault if !faulted => {
+                self.fsm
+                    .set_master_down_timer(self.master_down_interval, now);
+                self.fsm.state = State::Backup;
+                log::info!("({}) transitioned to BACKUP", self.name);
+                actions.push(Action::Publish(State::Backup));
+            }
+            // Let the BACKUPs see the new priority straight away rather
+            // than at the next advert.
+            State::Master => actions.push(Action::SendAdvert {
+                priority: self.effective_priority(),
+            }),
+            // A BACKUP waiting out `preempt_delay` that no longer outranks
+            // the MASTER has nothing left to preempt.
+            State::Backup => {
+                if let Some((priority, src)) = self.fsm.preempt_against
+                    && self.is_outranked_by(priority, src)
+                {
+                    self.fsm.disable_preempt_timer();
+                    log::info!(
+                        "({}) no longer outranks MASTER (priority {priority}); not preempting",
+                        self.name
+                    );
+                }
+            }
+            _ => {}
+        }
+    }
+
+    fn follow(
+        &mut self,
+        forced: State,
+        now: Instant,
+        actions: &mut Vec<Action>,
+    ) {
+        let current = self.fsm.state;
+        let faulted = self.tracked.is_faulted();
+        let Some(target) = sync::follow(current, forced, faulted) else {
+            return;
+        };
+        match target {
+            State::Master => self.become_master(now, actions),
+            State::Backup => {
+                self.fsm.disable_preempt_timer();
+                if current == State::Master {
+                    self.become_backup(now, actions);
+                } else {
+                    self.fsm
+                        .set_master_down_timer(self.master_down_interval, now);
+                    self.fsm.state = State::Backup;
+                }
+            }
+            State::Fault => {
+                if current == State::Master {
+                    actions.push(Action::DeleteAddresses);
+                }
+                self.fsm.disable_timer();
+                self.fsm.disable_preempt_timer();
+                self.fsm.state = State::Fault;
+            }
+            State::Init => {}
+        }
+        log::info!(
+            "({}) transitioned to {target:?} (sync group {})",
+            self.name,
+            self.sync_group
+                .as_ref()
+                .map_or("", |group| group.name.as_str())
+        );
+    }
+
+    fn release(&mut self, now: Instant, actions: &mut Vec<Action>) {
+        if self.fsm.state != State::Master {
+            return;
+        }
+        actions.push(Action::SendAdvert { priority: 0 });
+        self.released = true;
+        self.become_backup(now, actions);
+        log::info!(
+            "({}) released mastership, transitioned to BACKUP",
+            self.name
+        );
+        actions.push(Action::Publish(State::Backup));
+    }
 }
 
 #[cfg(test)]
 mod tests {
+    use std::net::Ipv4Addr;
+
     use super::*;
+    use crate::VrrpVersion;
+    use crate::config::InitialState;
+    use crate::router::tests::sample;
+
+    fn secs(secs: f32) -> Duration {
+        Duration::from_secs_f32(secs)
+    }
+
+    fn advert(priority: u8, src: [u8; 4]) -> Event {
+        Event::Advert(Advert {
+            priority,
+            adver_int_cs: 100,
+            src: IpAddr::V4(Ipv4Addr::from(src)),
+        })
+    }
+
+    fn master_src() -> IpAddr {
+        IpAddr::V4(Ipv4Addr::new(10, 0, 0, 9))
+    }
+
+    /// Priority 100, 1s adverts, preempting straight away; BACKUP from `now`.
+    fn backup(now: Instant) -> VirtualRouter {
+        let mut vr = sample(VrrpVersion::V3, 100);
+        vr.primary_ip = Ipv4Addr::new(10, 0, 0, 5);
+        vr.handle(Event::Startup, now);
+        vr
+    }
+
+    /// As `backup`, but MASTER from `now`.
+    fn master(now: Instant) -> VirtualRouter {
+        let mut vr = sample(VrrpVersion::V3, 100);
+        vr.primary_ip = Ipv4Addr::new(10, 0, 0, 5);
+        vr.initial_state = InitialState::Master;
+        vr.handle(Event::Startup, now);
+        vr
+    }
+
+    fn takeover(priority: u8) -> Vec<Action> {
+        vec![
+            Action::SendAdvert { priority },
+            Action::Announce,
+            Action::AddAddresses,
+        ]
+    }
 
     #[test]
     fn set_advert_timer_sets_type_and_duration() {
         let mut fsm = VirtualRouterMachine::default();
-        fsm.set_advert_timer(1.5);
+        let now = Instant::now();
+        fsm.set_advert_timer(1.5, now);
 
         assert_eq!(fsm.timer.t_type, TimerType::Adver);
         assert_eq!(fsm.timer.remaining_time, 1.5);
-        assert!(fsm.timer.waiting_for.is_some());
-        assert!(fsm.timer.waiting_for.unwrap() > Instant::now());
+        assert_eq!(fsm.timer.waiting_for, Some(now + secs(1.5)));
     }
 
     #[test]
     fn set_master_down_timer_sets_type_and_duration() {
         let mut fsm = VirtualRouterMachine::default();
-        fsm.set_master_down_timer(3.5);
+        let now = Instant::now();
+        fsm.set_master_down_timer(3.5, now);
 
         assert_eq!(fsm.timer.t_type, TimerType::MasterDown);
         assert_eq!(fsm.timer.remaining_time, 3.5);
-        assert!(fsm.timer.waiting_for.is_some());
-        assert!(fsm.timer.waiting_for.unwrap() > Instant::now());
+        assert_eq!(fsm.timer.waiting_for, Some(now + secs(3.5)));
     }
 
     #[test]
     fn disable_timer_clears_type_duration_and_deadline() {
         let mut fsm = VirtualRouterMachine::default();
-        fsm.set_advert_timer(1.0);
+        fsm.set_advert_timer(1.0, Instant::now());
 
         fsm.disable_timer();
 
@@ -112,10 +605,409 @@ mod tests {
     #[test]
     fn setting_a_new_timer_overwrites_the_previous_one() {
         let mut fsm = VirtualRouterMachine::default();
-        fsm.set_master_down_timer(3.0);
-        fsm.set_advert_timer(1.0);
+        fsm.set_master_down_timer(3.0, Instant::now());
+        fsm.set_advert_timer(1.0, Instant::now());
 
         assert_eq!(fsm.timer.t_type, TimerType::Adver);
         assert_eq!(fsm.timer.remaining_time, 1.0);
     }
+
+    #[test]
+    fn preempt_timer_runs_alongside_the_main_timer() {
+        let mut fsm = VirtualRouterMachine::default();
+        fsm.set_master_down_timer(3.0, Instant::now());
+        fsm.set_preempt_timer(1.0, 90, master_src(), Instant::now());
+
+        assert_eq!(fsm.timer.t_type, TimerType::MasterDown);
+        assert_eq!(fsm.preempt_timer.t_type, TimerType::Preempt);
+        assert_eq!(fsm.preempt_against, Some((90, master_src())));
+        assert_eq!(fsm.next_deadline(), fsm.preempt_timer.waiting_for);
+    }
+
+    #[test]
+    fn disable_preempt_timer_clears_it_and_its_master_priority() {
+        let mut fsm = VirtualRouterMachine::default();
+        fsm.set_master_down_timer(3.0, Instant::now());
+        fsm.set_preempt_timer(1.0, 90, master_src(), Instant::now());
+
+        fsm.disable_preempt_timer();
+
+        assert_eq!(fsm.preempt_timer.t_type, TimerType::Null);
+        assert!(fsm.preempt_against.is_none());
+        assert_eq!(fsm.next_deadline(), fsm.timer.waiting_for);
+    }
+
+    #[test]
+    fn startup_goes_to_backup_and_waits_for_the_master() {
+        let now = Instant::now();
+        let mut vr = sample(VrrpVersion::V3, 100);
+
+        let actions = vr.handle(Event::Startup, now);
+
+        assert_eq!(vr.fsm.state, State::Backup);
+        let master_down = now + secs(vr.master_down_interval);
+        assert_eq!(
+            actions,
+            vec![
+                Action::DeleteAddresses,
+                Action::Publish(State::Backup),
+                Action::DiscardVirtualMac,
+                Action::ArmTimer(Some(master_down)),
+            ]
+        );
+    }
+
+    #[test]
+    fn address_owner_starts_as_master() {
+        let now = Instant::now();
+        let mut vr = sample(VrrpVersion::V3, 100);
+        vr.set_address_owner();
+
+        let actions = vr.handle(Event::Startup, now);
+
+        assert_eq!(vr.fsm.state, State::Master);
+        assert_eq!(
+            actions,
+            vec![
+                Action::SendAdvert { priority: 255 },
+                Action::Announce,
+                Action::Publish(State::Master),
+                Action::AcceptVirtualMac,
+                Action::ArmTimer(Some(now + secs(1.0))),
+            ]
+        );
+    }
+
+    #[test]
+    fn owner_started_as_backup_holds_off_for_preempt_delay() {
+        let now = Instant::now();
+        let mut vr = sample(VrrpVersion::V3, 100);
+        vr.set_address_owner();
+        vr.initial_state = InitialState::Backup;
+        vr.preempt_delay = 5;
+        vr.handle(Event::Startup, now);
+
+        // Still hearing the MASTER it outranks, once a second.
+        for s in 1..6 {
+            vr.handle(advert(100, [10, 0, 0, 1]), now + secs(s as f32));
+            assert_eq!(vr.fsm.state, State::Backup);
+        }
+        let preempt_at = now + secs(6.0);
+        assert_eq!(vr.fsm.preempt_timer.waiting_for, Some(preempt_at));
+
+        vr.handle(Event::Timer, preempt_at);
+        assert_eq!(vr.fsm.state, State::Master);
+    }
+
+    #[test]
+    fn address_owner_never_adds_or_deletes_its_addresses() {
+        let now = Instant::now();
+        let mut vr = sample(VrrpVersion::V3, 100);
+        vr.set_address_owner();
+        vr.initial_state = InitialState::Backup;
+        let touches_addresses = |actions: &[Action]| {
+            actions.iter().any(|action| {
+                matches!(action, Action::AddAddresses | Action::DeleteAddresses)
+            })
+        };
+
+        let mut actions = vr.handle(Event::Startup, now);
+        let master_down = now + secs(vr.master_down_interval);
+        actions.extend(vr.handle(Event::Timer, master_down));
+        assert_eq!(vr.fsm.state, State::Master);
+        actions.extend(vr.handle(Event::Release, master_down));
+        assert_eq!(vr.fsm.state, State::Backup);
+        actions.extend(vr.handle(Event::Shutdown, master_down));
+
+        assert!(!touches_addresses(&actions), "{actions:?}");
+    }
+
+    #[test]
+    fn startup_delay_holds_in_init_until_its_timer() {
+        let now = Instant::now();
+        let mut vr = sample(VrrpVersion::V3, 100);
+        vr.startup_delay = 10;
+
+        let up = now + secs(10.0);
+        assert_eq!(
+            vr.handle(Event::Startup, now),
+            vec![Action::ArmTimer(Some(up))]
+        );
+        assert_eq!(vr.fsm.state, State::Init);
+        // Asking again doesn't restart the wait.
+        assert!(vr.handle(Event::Startup, now + secs(5.0)).is_empty());
+
+        vr.handle(Event::Timer, up);
+        assert_eq!(vr.fsm.state, State::Backup);
+    }
+
+    #[test]
+    fn faulted_router_starts_in_fault() {
+        let mut vr = sample(VrrpVersion::V3, 100);
+        vr.update_tracked("interface eth1", None, false);
+
+        let actions = vr.handle(Event::Startup, Instant::now());
+
+        assert_eq!(vr.fsm.state, State::Fault);
+        assert_eq!(
+            actions,
+            vec![Action::Publish(State::Fault), Action::DiscardVirtualMac]
+        );
+    }
+
+    #[test]
+    fn backup_takes_over_once_master_down_interval_passes() {
+        let now = Instant::now();
+        let mut vr = backup(now);
+        let master_down = now + secs(vr.master_down_interval);
+
+        assert!(vr.handle(Event::Timer, master_down - secs(0.1)).is_empty());
+        assert_eq!(vr.fsm.state, State::Backup);
+
+        let actions = vr.handle(Event::Timer, master_down);
+        assert_eq!(vr.fsm.state, State::Master);
+        let mut expected = takeover(100);
+        expected.push(Action::Publish(State::Master));
+        expected.push(Action::AcceptVirtualMac);
+        expected.push(Action::ArmTimer(Some(master_down + secs(1.0))));
+        assert_eq!(actions, expected);
+    }
+
+    #[test]
+    fn backup_hearing_a_higher_priority_master_stays_backup() {
+        let now = Instant::now();
+        let mut vr = backup(now);
+        let later = now + secs(2.0);
+
+        let actions = vr.handle(advert(150, [10, 0, 0, 1]), later);
+
+        assert_eq!(vr.fsm.state, State::Backup);
+        let master_down = later + secs(vr.master_down_interval);
+        assert_eq!(actions, vec![Action::ArmTimer(Some(master_down))]);
+    }
+
+    #[test]
+    fn backup_preempts_a_lower_priority_master() {
+        let now = Instant::now();
+        let mut vr = backup(now);
+
+        let actions = vr.handle(advert(50, [10, 0, 0, 9]), now);
+
+        assert_eq!(vr.fsm.state, State::Master);
+        assert!(actions.starts_with(&takeover(100)));
+        assert!(actions.contains(&Action::Publish(State::Master)));
+    }
+
+    #[test]
+    fn backup_without_preempt_mode_leaves_a_lower_priority_master() {
+        let now = Instant::now();
+        let mut vr = backup(now);
+        vr.preempt_mode = false;
+
+        vr.handle(advert(50, [10, 0, 0, 9]), now);
+
+        assert_eq!(vr.fsm.state, State::Backup);
+    }
+
+    #[test]
+    fn preempt_delay_holds_off_preempting() {
+        let now = Instant::now();
+        let mut vr = backup(now);
+        vr.preempt_delay = 5;
+
+        vr.handle(advert(50, [10, 0, 0, 9]), now);
+        assert_eq!(vr.fsm.state, State::Backup);
+        let preempt_at = now + secs(5.0);
+        assert_eq!(vr.fsm.preempt_timer.waiting_for, Some(preempt_at));
+
+        // The same MASTER carrying on doesn't restart the wait.
+        vr.handle(advert(50, [10, 0, 0, 9]), now + secs(1.0));
+        assert_eq!(vr.fsm.preempt_timer.waiting_for, Some(preempt_at));
+
+        let actions = vr.handle(Event::Timer, preempt_at);
+        assert_eq!(vr.fsm.state, State::Master);
+        assert!(actions.starts_with(&takeover(100)));
+    }
+
+    #[test]
+    fn losing_priority_during_preempt_delay_cancels_the_preempt() {
+        let now = Instant::now();
+        let mut vr = backup(now);
+        vr.preempt_delay = 2;
+        vr.handle(advert(80, [10, 0, 0, 9]), now);
+        assert!(vr.fsm.preempt_against.is_some());
+
+        vr.update_tracked("interface eth1", Some(40), false);
+        vr.handle(Event::PriorityChange, now + secs(1.0));
+        assert!(vr.fsm.preempt_against.is_none());
+
+        vr.handle(Event::Timer, now + secs(2.0));
+        assert_eq!(vr.fsm.state, State::Backup);
+    }
+
+    #[test]
+    fn preempt_timer_only_takes_over_while_still_outranking() {
+        let now = Instant::now();
+        let mut vr = backup(now);
+        vr.preempt_delay = 2;
+        vr.handle(advert(80, [10, 0, 0, 9]), now);
+
+        // Lowered, with the `PriorityChange` still queued behind the timer.
+        vr.update_tracked("interface eth1", Some(40), false);
+        vr.handle(Event::Timer, now + secs(2.0));
+
+        assert_eq!(vr.fsm.state, State::Backup);
+        assert!(vr.fsm.preempt_against.is_none());
+    }
+
+    #[test]
+    fn backup_takes_over_after_skew_time_when_the_master_resigns() {
+        let now = Instant::now();
+        let mut vr = backup(now);
+
+        let actions = vr.handle(advert(0, [10, 0, 0, 9]), now);
+
+        let skew = now + secs(vr.skew_time);
+        assert_eq!(actions, vec![Action::ArmTimer(Some(skew))]);
+    }
+
+    #[test]
+    fn master_sends_an_advert_every_interval() {
+        let now = Instant::now();
+        let mut vr = master(now);
+        let due = now + secs(1.0);
+
+        let actions = vr.handle(Event::Timer, due);
+
+        assert_eq!(
+            actions,
+            vec![
+                Action::SendAdvert { priority: 100 },
+                Action::ArmTimer(Some(due + secs(1.0))),
+            ]
+        );
+    }
+
+    #[test]
+    fn master_yields_to_a_higher_priority() {
+        let now = Instant::now();
+        let mut vr = master(now);
+
+        let actions = vr.handle(advert(200, [10, 0, 0, 1]), now);
+
+        assert_eq!(vr.fsm.state, State::Backup);
+        assert_eq!(
+            actions,
+            vec![
+                Action::DeleteAddresses,
+                Action::Publish(State::Backup),
+                Action::DiscardVirtualMac,
+                Action::ArmTimer(Some(now + secs(vr.master_down_interval))),
+            ]
+        );
+    }
+
+    #[test]
+    fn equal_priority_masters_yield_to_the_higher_address() {
+        let now = Instant::now();
+        let mut vr = master(now);
+        assert!(vr.handle(advert(100, [10, 0, 0, 1]), now).is_empty());
+        assert_eq!(vr.fsm.state, State::Master);
+
+        vr.handle(advert(100, [10, 0, 0, 9]), now);
+        assert_eq!(vr.fsm.state, State::Backup);
+    }
+
+    #[test]
+    fn master_answers_a_resignation_with_an_advert() {
+        let now = Instant::now();
+        let mut vr = master(now);
+        let later = now + secs(0.5);
+
+        let actions = vr.handle(advert(0, [10, 0, 0, 9]), later);
+
+        assert_eq!(
+            actions,
+            vec![
+                Action::SendAdvert { priority: 100 },
+                Action::ArmTimer(Some(later + secs(1.0))),
+            ]
+        );
+    }
+
+    #[test]
+    fn released_master_resigns_and_holds_off_preempting() {
+        let now = Instant::now();
+        let mut vr = master(now);
+
+        let actions = vr.handle(Event::Release, now);
+        assert_eq!(vr.fsm.state, State::Backup);
+        assert_eq!(
+            actions[..2],
+            [Action::SendAdvert { priority: 0 }, Action::DeleteAddresses]
+        );
+
+        vr.handle(advert(50, [10, 0, 0, 9]), now);
+        assert_eq!(vr.fsm.state, State::Backup);
+    }
+
+    #[test]
+    fn failing_tracked_object_faults_the_master() {
+        let now = Instant::now();
+        let mut vr = master(now);
+        vr.update_tracked("interface eth1", None, false);
+
+        let actions = vr.handle(Event::PriorityChange, now);
+
+        assert_eq!(vr.fsm.state, State::Fault);
+        assert_eq!(
+            actions,
+            vec![
+                Action::DeleteAddresses,
+                Action::Publish(State::Fault),
+                Action::DiscardVirtualMac,
+                Action::ArmTimer(None),
+            ]
+        );
+    }
+
+    #[test]
+    fn lowered_priority_is_advertised_straight_away() {
+        let now = Instant::now();
+        let mut vr = master(now);
+        vr.update_tracked("interface eth1", Some(30), false);
+
+        let actions = vr.handle(Event::PriorityChange, now);
+
+        assert_eq!(actions, vec![Action::SendAdvert { priority: 70 }]);
+    }
+
+    #[test]
+    fn master_resigns_on_shutdown() {
+        let now = Instant::now();
+        let mut vr = master(now);
+
+        let actions = vr.handle(Event::Shutdown, now);
+
+        assert_eq!(vr.fsm.state, State::Init);
+        assert_eq!(
+            actions,
+            vec![
+                Action::SendAdvert { priority: 0 },
+                Action::DeleteAddresses,
+                Action::ArmTimer(None),
+            ]
+        );
+    }
+
+    #[test]
+    fn forced_transitions_are_not_published() {
+        let now = Instant::now();
+        let mut vr = backup(now);
+
+        let actions = vr.handle(Event::Sync(State::Master), now);
+
+        assert_eq!(vr.fsm.state, State::Master);
+        assert!(!actions.iter().any(|a| matches!(a, Action::Publish(_))));
+    }
 }
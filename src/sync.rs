@@ -0,0 +1,138 @@
+//! VRRP sync groups: instances (e.g. a WAN-side and a LAN-side VR) that
+//! have to be in the same state on a box. Each member publishes the
+//! transitions it makes of its own accord; every other member follows them
+//! through `Event::Sync`.
+use tokio::sync::broadcast;
+
+use crate::state_machine::State;
+
+/// Transitions are rare; this only has to absorb a burst from every member
+/// of a group at once.
+const CHANNEL_CAPACITY: usize = 16;
+
+#[derive(Debug, Clone)]
+pub(crate) struct SyncMessage {
+    /// Name of the member that made the transition.
+    pub(crate) from: String,
+    pub(crate) state: State,
+}
+
+/// One member's connection to its sync group, shared (cloned) across every
+/// member of the group by `general::configs_to_vrs`.
+#[derive(Debug, Clone)]
+pub(crate) struct SyncGroupHandle {
+    pub(crate) name: String,
+    tx: broadcast::Sender<SyncMessage>,
+}
+
+impl SyncGroupHandle {
+    pub(crate) fn new(name: &str) -> Self {
+        let (tx, _) = broadcast::channel(CHANNEL_CAPACITY);
+        Self {
+            name: name.to_string(),
+            tx,
+        }
+    }
+
+    pub(crate) fn publish(&self, from: &str, state: State) {
+        // Nobody listening (e.g. the other members failed to start) isn't
+        // an error.
+        let _ = self.tx.send(SyncMessage {
+            from: from.to_string(),
+            state,
+        });
+    }
+
+    pub(crate) fn subscribe(&self) -> broadcast::Receiver<SyncMessage> {
+        self.tx.subscribe()
+    }
+}
+
+/// The state a member currently in `current` is forced into when another
+/// member of its group moves to `forced`, if any. A member whose own
+/// tracked objects fault it (`faulted`) stays in FAULT, and one still in
+/// INIT is left to its own startup transition.
+pub(crate) fn follow(
+    current: State,
+    forced: State,
+    faulted: bool,
+) -> Option<State> {
+    match (current, forced) {
+        (State::Backup, State::Master) => Some(State::Master),
+        (State::Master, State::Backup) => Some(State::Backup),
+        (State::Master | State::Backup, State::Fault) => Some(State::Fault),
+        // Leaving FAULT always goes through BACKUP, so the group's MASTER
+        // is elected by the protocol rather than forced.
+        (State::Fault, State::Backup | State::Master) if !faulted => {
+            Some(State::Backup)
+        }
+        _ => None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use futures_util::FutureExt;
+
+    use super::*;
+
+    #[test]
+    fn members_receive_published_transitions() {
+        let group = SyncGroupHandle::new("nat");
+        let mut rx = group.clone().subscribe();
+
+        group.publish("VR_WAN", State::Master);
+
+        let msg = rx.recv().now_or_never().unwrap().unwrap();
+        assert_eq!(msg.from, "VR_WAN");
+        assert_eq!(msg.state, State::Master);
+    }
+
+    #[test]
+    fn publishing_without_members_listening_is_fine() {
+        SyncGroupHandle::new("nat").publish("VR_WAN", State::Fault);
+    }
+
+    #[test]
+    fn backup_and_master_follow_each_other() {
+        assert_eq!(
+            follow(State::Backup, State::Master, false),
+            Some(State::Master)
+        );
+        assert_eq!(
+            follow(State::Master, State::Backup, false),
+            Some(State::Backup)
+        );
+        assert_eq!(follow(State::Master, State::Master, false), None);
+    }
+
+    #[test]
+    fn fault_spreads_to_the_whole_group() {
+        assert_eq!(
+            follow(State::Master, State::Fault, false),
+            Some(State::Fault)
+        );
+        assert_eq!(
+            follow(State::Backup, State::Fault, false),
+            Some(State::Fault)
+        );
+    }
+
+    #[test]
+    fn recovery_from_fault_goes_through_backup() {
+        assert_eq!(
+            follow(State::Fault, State::Master, false),
+            Some(State::Backup)
+        );
+        assert_eq!(
+            follow(State::Fault, State::Backup, false),
+            Some(State::Backup)
+        );
+    }
+
+    #[test]
+    fn own_fault_and_init_are_left_alone() {
+        assert_eq!(follow(State::Fault, State::Backup, true), None);
+        assert_eq!(follow(State::Init, State::Master, false), None);
+    }
+}
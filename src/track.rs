@@ -0,0 +1,172 @@
+//! Bookkeeping for the objects a virtual router tracks (interfaces, health
+//! check scripts, kernel routes, and anything else that should move its
+//! priority). Each failing object either lowers the effective priority by
+//! its weight, or -- when it has no weight -- forces the router into FAULT.
+use std::collections::BTreeMap;
+
+#[derive(Debug, Clone, Default)]
+pub(crate) struct TrackedObjects {
+    /// Currently failing objects, keyed by a human-readable name such as
+    /// `interface eth1`, with the weight each one subtracts (`None` faults
+    /// the router instead).
+    failing: BTreeMap<String, Option<u8>>,
+}
+
+impl TrackedObjects {
+    /// Records whether `key` is healthy. Returns whether that changed
+    /// anything.
+    pub(crate) fn set(
+        &mut self,
+        key: &str,
+        weight: Option<u8>,
+        healthy: bool,
+    ) -> bool {
+        if healthy {
+            self.failing.remove(key).is_some()
+        } else {
+            self.failing.insert(key.to_string(), weight) != Some(weight)
+        }
+    }
+
+    /// Total weight subtracted from the configured priority.
+    pub(crate) fn penalty(&self) -> u16 {
+        self.failing.values().flatten().map(|w| *w as u16).sum()
+    }
+
+    /// Whether any failing object has no weight, which faults the router.
+    pub(crate) fn is_faulted(&self) -> bool {
+        self.failing.values().any(Option::is_none)
+    }
+
+    pub(crate) fn failing(&self) -> impl Iterator<Item = &str> {
+        self.failing.keys().map(String::as_str)
+    }
+}
+
+/// `priority` lowered by `penalty`, kept within 1-254: 0 is reserved for a
+/// MASTER resigning and 255 for the address owner.
+pub(crate) fn effective_priority(priority: u8, penalty: u16) -> u8 {
+    (priority as u16).saturating_sub(penalty).clamp(1, 254) as u8
+}
+
+/// Rise/fall debouncing of a track script's raw pass/fail results.
+#[derive(Debug, Clone)]
+pub(crate) struct ScriptHealth {
+    rise: u8,
+    fall: u8,
+    /// `None` until the first result, which is taken as-is.
+    healthy: Option<bool>,
+    /// Consecutive results disagreeing with `healthy`.
+    streak: u8,
+}
+
+impl ScriptHealth {
+    pub(crate) fn new(rise: u8, fall: u8) -> Self {
+        Self {
+            rise,
+            fall,
+            healthy: None,
+            streak: 0,
+        }
+    }
+
+    /// Feeds in one run's result. Returns the new health when it flips
+    /// (or on the very first result), `None` otherwise.
+    pub(crate) fn record(&mut self, success: bool) -> Option<bool> {
+        let Some(healthy) = self.healthy else {
+            self.healthy = Some(success);
+            return Some(success);
+        };
+        if success == healthy {
+            self.streak = 0;
+            return None;
+        }
+
+        self.streak += 1;
+        let needed = if success { self.rise } else { self.fall };
+        if self.streak < needed {
+            return None;
+        }
+        self.streak = 0;
+        self.healthy = Some(success);
+        Some(success)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn failing_weighted_objects_add_up() {
+        let mut tracked = TrackedObjects::default();
+        assert!(tracked.set("interface eth1", Some(20), false));
+        assert!(tracked.set("interface eth2", Some(30), false));
+
+        assert_eq!(tracked.penalty(), 50);
+        assert!(!tracked.is_faulted());
+    }
+
+    #[test]
+    fn recovering_object_drops_its_weight() {
+        let mut tracked = TrackedObjects::default();
+        tracked.set("interface eth1", Some(20), false);
+
+        assert!(tracked.set("interface eth1", Some(20), true));
+        assert_eq!(tracked.penalty(), 0);
+        assert!(!tracked.set("interface eth1", Some(20), true));
+    }
+
+    #[test]
+    fn repeated_failure_is_not_a_change() {
+        let mut tracked = TrackedObjects::default();
+        assert!(tracked.set("interface eth1", Some(20), false));
+        assert!(!tracked.set("interface eth1", Some(20), false));
+    }
+
+    #[test]
+    fn unweighted_failure_faults() {
+        let mut tracked = TrackedObjects::default();
+        tracked.set("interface eth1", None, false);
+
+        assert!(tracked.is_faulted());
+        assert_eq!(tracked.penalty(), 0);
+    }
+
+    #[test]
+    fn effective_priority_stays_within_1_to_254() {
+        assert_eq!(effective_priority(100, 20), 80);
+        assert_eq!(effective_priority(100, 300), 1);
+        assert_eq!(effective_priority(254, 0), 254);
+    }
+
+    #[test]
+    fn first_script_result_is_taken_as_is() {
+        let mut health = ScriptHealth::new(3, 3);
+        assert_eq!(health.record(false), Some(false));
+    }
+
+    #[test]
+    fn script_needs_fall_failures_then_rise_successes() {
+        let mut health = ScriptHealth::new(2, 3);
+        health.record(true);
+
+        assert_eq!(health.record(false), None);
+        assert_eq!(health.record(false), None);
+        assert_eq!(health.record(false), Some(false));
+
+        assert_eq!(health.record(true), None);
+        assert_eq!(health.record(true), Some(true));
+    }
+
+    #[test]
+    fn interrupted_streak_starts_over() {
+        let mut health = ScriptHealth::new(1, 2);
+        health.record(true);
+
+        assert_eq!(health.record(false), None);
+        assert_eq!(health.record(true), None);
+        assert_eq!(health.record(false), None);
+        assert_eq!(health.record(false), Some(false));
+    }
+}